@@ -0,0 +1,62 @@
+//! Throughput of `Message::from_json` against `fast::decode` on `full` channel frames.
+//!
+//! Uses `benches/data/full.jsonl`: synthetic frames in the `replay` recording format, not
+//! captured from Coinbase. Set `CB_RECORDING` to a file written by
+//! `WSFeed::connect_with_sub_recorded` to use real data.
+
+use coinbase_pro_rs::fast::{decode, ProductIds};
+use coinbase_pro_rs::replay::RecordedFrame;
+use coinbase_pro_rs::structs::wsfeed::Message;
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+
+fn frames() -> Vec<String> {
+    let data = match std::env::var("CB_RECORDING") {
+        Ok(path) => std::fs::read_to_string(path).expect("cannot read CB_RECORDING"),
+        Err(_) => include_str!("data/full.jsonl").to_string(),
+    };
+    data.lines()
+        .map(|x| serde_json::from_str::<RecordedFrame>(x).unwrap().frame)
+        .collect()
+}
+
+fn bench_decode(c: &mut Criterion) {
+    let frames = frames();
+    let bytes: usize = frames.iter().map(|x| x.len()).sum();
+
+    for (name, throughput) in [
+        ("decode_bytes", Throughput::Bytes(bytes as u64)),
+        ("decode_messages", Throughput::Elements(frames.len() as u64)),
+    ]
+    .iter()
+    {
+        let mut group = c.benchmark_group(*name);
+        group.throughput(throughput.clone());
+        group.bench_function("from_json", |b| {
+            b.iter(|| {
+                for frame in &frames {
+                    black_box(Message::from_json(frame).unwrap());
+                }
+            })
+        });
+        group.bench_function("fast", |b| {
+            b.iter(|| {
+                for frame in &frames {
+                    black_box(decode(frame).unwrap());
+                }
+            })
+        });
+        group.bench_function("fast_interned", |b| {
+            let mut ids = ProductIds::new();
+            b.iter(|| {
+                for frame in &frames {
+                    let msg = decode(frame).unwrap();
+                    black_box(msg.product_id().map(|x| ids.intern(x)));
+                }
+            })
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_decode);
+criterion_main!(benches);
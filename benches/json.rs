@@ -0,0 +1,54 @@
+//! Throughput of `Message::from_json` on `full` and `level2` frames with the selected
+//! JSON backend. Compare backends with criterion baselines:
+//!
+//! ```text
+//! cargo bench --bench json -- --save-baseline serde_json
+//! cargo bench --bench json --features simd-json -- --baseline serde_json
+//! ```
+//!
+//! Uses synthetic frames of `benches/data`, not captured from Coinbase. Set `CB_FULL_RECORDING`
+//! or `CB_LEVEL2_RECORDING` to a file written by `WSFeed::connect_with_sub_recorded` to use
+//! real data.
+
+use coinbase_pro_rs::replay::RecordedFrame;
+use coinbase_pro_rs::structs::wsfeed::Message;
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+
+fn frames(var: &str, default: &str) -> Vec<String> {
+    let data = match std::env::var(var) {
+        Ok(path) => std::fs::read_to_string(path).expect("cannot read recording"),
+        Err(_) => default.to_string(),
+    };
+    data.lines()
+        .map(|x| serde_json::from_str::<RecordedFrame>(x).unwrap().frame)
+        .collect()
+}
+
+fn bench_json(c: &mut Criterion) {
+    let recordings = [
+        (
+            "json_full",
+            frames("CB_FULL_RECORDING", include_str!("data/full.jsonl")),
+        ),
+        (
+            "json_level2",
+            frames("CB_LEVEL2_RECORDING", include_str!("data/level2.jsonl")),
+        ),
+    ];
+    for (name, frames) in recordings.iter() {
+        let bytes: usize = frames.iter().map(|x| x.len()).sum();
+        let mut group = c.benchmark_group(*name);
+        group.throughput(Throughput::Bytes(bytes as u64));
+        group.bench_function("from_json", |b| {
+            b.iter(|| {
+                for frame in frames {
+                    black_box(Message::from_json(frame).unwrap());
+                }
+            })
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_json);
+criterion_main!(benches);
@@ -0,0 +1,409 @@
+//! Contains async traits which abstract Public and Private sections of Coinbase api
+//!
+//! [`PublicApi`] and [`PrivateApi`] are implemented by `Public<ASync>` and `Private<ASync>`,
+//! so code written against the traits can be driven by the real client, the sandbox
+//! or any mock/simulator implementing the same interface. Both traits are object safe, so
+//! `Box<dyn PublicApi>` can be chosen at runtime.
+
+use async_trait::async_trait;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::structs::private::*;
+use crate::structs::public::*;
+use crate::structs::{reqs, DateTime};
+use crate::{ASync, CBError, Private, Public};
+
+/// Market data operations of the Public section
+#[async_trait]
+pub trait PublicApi: Send + std::marker::Sync {
+    async fn get_time(&self) -> Result<Time, CBError>;
+
+    async fn get_products(&self) -> Result<Vec<Product>, CBError>;
+
+    async fn get_product(&self, product_id: &str) -> Result<Product, CBError>;
+
+    async fn get_book_l1(&self, product_id: &str) -> Result<Book<BookRecordL1>, CBError>;
+
+    async fn get_book_l2(&self, product_id: &str) -> Result<Book<BookRecordL2>, CBError>;
+
+    async fn get_book_l3(&self, product_id: &str) -> Result<Book<BookRecordL3>, CBError>;
+
+    async fn get_ticker(&self, product_id: &str) -> Result<Ticker, CBError>;
+
+    async fn get_trades(&self, product_id: &str) -> Result<Vec<Trade>, CBError>;
+
+    async fn get_trades_paginated(
+        &self,
+        product_id: &str,
+        before: Option<usize>,
+        after: Option<usize>,
+        limit: Option<usize>,
+    ) -> Result<Vec<Trade>, CBError>;
+
+    async fn get_candles(
+        &self,
+        product_id: &str,
+        start: Option<DateTime>,
+        end: Option<DateTime>,
+        granularity: Granularity,
+    ) -> Result<Vec<Candle>, CBError>;
+
+    async fn get_candles_range(
+        &self,
+        product_id: &str,
+        start: DateTime,
+        end: DateTime,
+        granularity: Granularity,
+    ) -> Result<CandleHistory, CBError>;
+
+    async fn get_stats24h(&self, product_id: &str) -> Result<Stats24H, CBError>;
+
+    async fn get_all_stats(&self) -> Result<HashMap<String, ProductStats>, CBError>;
+
+    async fn get_currencies(&self) -> Result<Vec<Currency>, CBError>;
+
+    async fn get_currency(&self, currency_id: &str) -> Result<Currency, CBError>;
+}
+
+/// Account, order and fill operations of the Private section
+///
+/// Only [`set_order`](PrivateApi::set_order) has to be implemented to get all
+/// `buy_*`/`sell_*` helpers, they build the request with [`reqs::Order`].
+#[async_trait]
+pub trait PrivateApi: Send + std::marker::Sync {
+    async fn get_accounts(&self) -> Result<Vec<Account>, CBError>;
+
+    async fn get_account(&self, account_id: Uuid) -> Result<Account, CBError>;
+
+    async fn get_account_hist(&self, id: Uuid) -> Result<Vec<AccountHistory>, CBError>;
+
+    async fn get_account_holds(&self, id: Uuid) -> Result<Vec<AccountHolds>, CBError>;
+
+    async fn set_order(&self, order: reqs::Order<'_>) -> Result<Order, CBError>;
+
+    async fn buy_limit(
+        &self,
+        product_id: &str,
+        size: f64,
+        price: f64,
+        post_only: bool,
+    ) -> Result<Order, CBError> {
+        self.set_order(reqs::Order::buy_limit(product_id, size, price, post_only))
+            .await
+    }
+
+    async fn sell_limit(
+        &self,
+        product_id: &str,
+        size: f64,
+        price: f64,
+        post_only: bool,
+    ) -> Result<Order, CBError> {
+        self.set_order(reqs::Order::sell_limit(product_id, size, price, post_only))
+            .await
+    }
+
+    async fn buy_market(&self, product_id: &str, size: f64) -> Result<Order, CBError> {
+        self.set_order(reqs::Order::buy_market(product_id, size))
+            .await
+    }
+
+    async fn buy_market_funds(&self, product_id: &str, funds: f64) -> Result<Order, CBError> {
+        self.set_order(reqs::Order::buy_market_funds(product_id, funds))
+            .await
+    }
+
+    async fn sell_market(&self, product_id: &str, size: f64) -> Result<Order, CBError> {
+        self.set_order(reqs::Order::sell_market(product_id, size))
+            .await
+    }
+
+    async fn sell_market_funds(&self, product_id: &str, funds: f64) -> Result<Order, CBError> {
+        self.set_order(reqs::Order::sell_market_funds(product_id, funds))
+            .await
+    }
+
+    async fn cancel_order(&self, id: Uuid) -> Result<Uuid, CBError>;
+
+    async fn cancel_all(&self, product_id: Option<&str>) -> Result<Vec<Uuid>, CBError>;
+
+    async fn get_orders(
+        &self,
+        status: Option<OrderStatus>,
+        product_id: Option<&str>,
+    ) -> Result<Vec<Order>, CBError>;
+
+    async fn get_order(&self, id: Uuid) -> Result<Order, CBError>;
+
+    async fn get_fills(
+        &self,
+        order_id: Option<Uuid>,
+        product_id: Option<&str>,
+    ) -> Result<Vec<Fill>, CBError>;
+
+    async fn get_trailing_volume(&self) -> Result<Vec<TrailingVolume>, CBError>;
+
+    async fn get_fees(&self) -> Result<Fees, CBError>;
+
+    async fn get_transfers(
+        &self,
+        transfer_type: Option<TransferType>,
+        profile_id: Option<String>,
+        before: Option<DateTime>,
+        after: Option<DateTime>,
+        limit: Option<usize>,
+    ) -> Result<Vec<Transfer>, CBError>;
+}
+
+#[async_trait]
+impl PublicApi for Public<ASync> {
+    async fn get_time(&self) -> Result<Time, CBError> {
+        Public::get_time(self).await
+    }
+
+    async fn get_products(&self) -> Result<Vec<Product>, CBError> {
+        Public::get_products(self).await
+    }
+
+    async fn get_product(&self, product_id: &str) -> Result<Product, CBError> {
+        Public::get_product(self, product_id).await
+    }
+
+    async fn get_book_l1(&self, product_id: &str) -> Result<Book<BookRecordL1>, CBError> {
+        Public::get_book(self, product_id).await
+    }
+
+    async fn get_book_l2(&self, product_id: &str) -> Result<Book<BookRecordL2>, CBError> {
+        Public::get_book(self, product_id).await
+    }
+
+    async fn get_book_l3(&self, product_id: &str) -> Result<Book<BookRecordL3>, CBError> {
+        Public::get_book(self, product_id).await
+    }
+
+    async fn get_ticker(&self, product_id: &str) -> Result<Ticker, CBError> {
+        Public::get_ticker(self, product_id).await
+    }
+
+    async fn get_trades(&self, product_id: &str) -> Result<Vec<Trade>, CBError> {
+        Public::get_trades(self, product_id).await
+    }
+
+    async fn get_trades_paginated(
+        &self,
+        product_id: &str,
+        before: Option<usize>,
+        after: Option<usize>,
+        limit: Option<usize>,
+    ) -> Result<Vec<Trade>, CBError> {
+        Public::get_trades_paginated(self, product_id, before, after, limit).await
+    }
+
+    async fn get_candles(
+        &self,
+        product_id: &str,
+        start: Option<DateTime>,
+        end: Option<DateTime>,
+        granularity: Granularity,
+    ) -> Result<Vec<Candle>, CBError> {
+        Public::get_candles(self, product_id, start, end, granularity).await
+    }
+
+    async fn get_candles_range(
+        &self,
+        product_id: &str,
+        start: DateTime,
+        end: DateTime,
+        granularity: Granularity,
+    ) -> Result<CandleHistory, CBError> {
+        Public::get_candles_range(self, product_id, start, end, granularity).await
+    }
+
+    async fn get_stats24h(&self, product_id: &str) -> Result<Stats24H, CBError> {
+        Public::get_stats24h(self, product_id).await
+    }
+
+    async fn get_all_stats(&self) -> Result<HashMap<String, ProductStats>, CBError> {
+        Public::get_all_stats(self).await
+    }
+
+    async fn get_currencies(&self) -> Result<Vec<Currency>, CBError> {
+        Public::get_currencies(self).await
+    }
+
+    async fn get_currency(&self, currency_id: &str) -> Result<Currency, CBError> {
+        Public::get_currency(self, currency_id).await
+    }
+}
+
+#[async_trait]
+impl PrivateApi for Private<ASync> {
+    async fn get_accounts(&self) -> Result<Vec<Account>, CBError> {
+        Private::get_accounts(self).await
+    }
+
+    async fn get_account(&self, account_id: Uuid) -> Result<Account, CBError> {
+        Private::get_account(self, account_id).await
+    }
+
+    async fn get_account_hist(&self, id: Uuid) -> Result<Vec<AccountHistory>, CBError> {
+        Private::get_account_hist(self, id).await
+    }
+
+    async fn get_account_holds(&self, id: Uuid) -> Result<Vec<AccountHolds>, CBError> {
+        Private::get_account_holds(self, id).await
+    }
+
+    async fn set_order(&self, order: reqs::Order<'_>) -> Result<Order, CBError> {
+        Private::set_order(self, order).await
+    }
+
+    async fn cancel_order(&self, id: Uuid) -> Result<Uuid, CBError> {
+        Private::cancel_order(self, id).await
+    }
+
+    async fn cancel_all(&self, product_id: Option<&str>) -> Result<Vec<Uuid>, CBError> {
+        Private::cancel_all(self, product_id).await
+    }
+
+    async fn get_orders(
+        &self,
+        status: Option<OrderStatus>,
+        product_id: Option<&str>,
+    ) -> Result<Vec<Order>, CBError> {
+        Private::get_orders(self, status, product_id).await
+    }
+
+    async fn get_order(&self, id: Uuid) -> Result<Order, CBError> {
+        Private::get_order(self, id).await
+    }
+
+    async fn get_fills(
+        &self,
+        order_id: Option<Uuid>,
+        product_id: Option<&str>,
+    ) -> Result<Vec<Fill>, CBError> {
+        Private::get_fills(self, order_id, product_id).await
+    }
+
+    async fn get_trailing_volume(&self) -> Result<Vec<TrailingVolume>, CBError> {
+        Private::get_trailing_volume(self).await
+    }
+
+    async fn get_fees(&self) -> Result<Fees, CBError> {
+        Private::get_fees(self).await
+    }
+
+    async fn get_transfers(
+        &self,
+        transfer_type: Option<TransferType>,
+        profile_id: Option<String>,
+        before: Option<DateTime>,
+        after: Option<DateTime>,
+        limit: Option<usize>,
+    ) -> Result<Vec<Transfer>, CBError> {
+        Private::get_transfers(self, transfer_type, profile_id, before, after, limit).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SANDBOX_URL;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_public_impl() {
+        fn is_public_api<T: PublicApi>() {}
+        is_public_api::<Public<ASync>>();
+    }
+
+    struct OrdersMock(Mutex<Vec<String>>);
+
+    #[async_trait]
+    impl PrivateApi for OrdersMock {
+        async fn get_accounts(&self) -> Result<Vec<Account>, CBError> {
+            unimplemented!()
+        }
+        async fn get_account(&self, _: Uuid) -> Result<Account, CBError> {
+            unimplemented!()
+        }
+        async fn get_account_hist(&self, _: Uuid) -> Result<Vec<AccountHistory>, CBError> {
+            unimplemented!()
+        }
+        async fn get_account_holds(&self, _: Uuid) -> Result<Vec<AccountHolds>, CBError> {
+            unimplemented!()
+        }
+        async fn set_order(&self, order: reqs::Order<'_>) -> Result<Order, CBError> {
+            self.0
+                .lock()
+                .unwrap()
+                .push(serde_json::to_string(&order).unwrap());
+            Err(CBError::Null)
+        }
+        async fn cancel_order(&self, _: Uuid) -> Result<Uuid, CBError> {
+            unimplemented!()
+        }
+        async fn cancel_all(&self, _: Option<&str>) -> Result<Vec<Uuid>, CBError> {
+            unimplemented!()
+        }
+        async fn get_orders(
+            &self,
+            _: Option<OrderStatus>,
+            _: Option<&str>,
+        ) -> Result<Vec<Order>, CBError> {
+            unimplemented!()
+        }
+        async fn get_order(&self, _: Uuid) -> Result<Order, CBError> {
+            unimplemented!()
+        }
+        async fn get_fills(&self, _: Option<Uuid>, _: Option<&str>) -> Result<Vec<Fill>, CBError> {
+            unimplemented!()
+        }
+        async fn get_trailing_volume(&self) -> Result<Vec<TrailingVolume>, CBError> {
+            unimplemented!()
+        }
+        async fn get_fees(&self) -> Result<Fees, CBError> {
+            unimplemented!()
+        }
+        async fn get_transfers(
+            &self,
+            _: Option<TransferType>,
+            _: Option<String>,
+            _: Option<DateTime>,
+            _: Option<DateTime>,
+            _: Option<usize>,
+        ) -> Result<Vec<Transfer>, CBError> {
+            unimplemented!()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_private_mock() {
+        let mock = OrdersMock(Mutex::new(vec![]));
+        let res = mock.buy_limit("BTC-USD", 1.0, 100.0, true).await;
+        assert!(matches!(res, Err(CBError::Null)));
+        mock.sell_market_funds("BTC-USD", 10.0).await.unwrap_err();
+
+        let orders = mock.0.lock().unwrap();
+        assert_eq!(2, orders.len());
+        assert!(orders[0].contains(r#""side":"buy""#));
+        assert!(orders[0].contains(r#""type":"limit""#));
+        assert!(orders[1].contains(r#""side":"sell""#));
+        assert!(orders[1].contains(r#""funds":10.0"#));
+    }
+
+    #[test]
+    fn test_dyn() {
+        let public: Box<dyn PublicApi> = Box::new(Public::<ASync>::new(SANDBOX_URL));
+        let private: Box<dyn PrivateApi> = Box::new(OrdersMock(Mutex::new(vec![])));
+        drop((public, private));
+    }
+
+    #[test]
+    fn test_private_impl() {
+        fn is_private_api<T: PrivateApi>() {}
+        is_private_api::<Private<ASync>>();
+    }
+}
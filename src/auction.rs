@@ -0,0 +1,140 @@
+//! Contains tracker of products in auction mode
+//!
+//! New listings and re-enabled products open through an auction: orders are collected without
+//! matching and the `auctionfeed` channel reports indicative open price and size.
+//! [`AuctionTracker`] keeps the latest [`Auction`] message per product and follows
+//! `auction_mode` from the `status` channel or `get_products`, so strategies can ask whether
+//! a product is in auction and at which price it is going to open.
+//!
+//! ```no_run
+//! use coinbase_pro_rs::{auction::AuctionTracker, structs::wsfeed::*, WSFeed, WS_URL};
+//! use futures::TryStreamExt;
+//!
+//! # async fn run() {
+//! let channels = [ChannelType::Auctionfeed, ChannelType::Status];
+//! let stream = WSFeed::connect(WS_URL, &["BTC-USD"], &channels).await.unwrap();
+//! let mut tracker = AuctionTracker::new();
+//! stream
+//!     .try_for_each(|msg| {
+//!         tracker.push_message(&msg);
+//!         println!("{:?}", tracker.indicative_open("BTC-USD"));
+//!         futures::future::ok(())
+//!     })
+//!     .await
+//!     .unwrap();
+//! # }
+//! ```
+
+use std::collections::{HashMap, HashSet};
+
+use crate::structs::public::Product;
+use crate::structs::wsfeed::{Auction, Message};
+
+#[derive(Debug, Clone, Default)]
+pub struct AuctionTracker {
+    in_auction: HashSet<String>,
+    auctions: HashMap<String, Auction>,
+}
+
+impl AuctionTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Follows `auction_mode` of the product, eg: from `get_products`
+    pub fn push_product(&mut self, product: &Product) {
+        self.set_auction_mode(&product.id, product.auction_mode);
+    }
+
+    /// Applies `Message::Auction` and `Message::Status`, skips others
+    pub fn push_message(&mut self, msg: &Message) {
+        match msg {
+            Message::Auction(auction) => self.push_auction(auction),
+            Message::Status { products, .. } => {
+                for product in products {
+                    self.set_auction_mode(&product.id, product.auction_mode);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Keeps the latest auction message by sequence
+    pub fn push_auction(&mut self, auction: &Auction) {
+        self.in_auction.insert(auction.product_id.clone());
+        match self.auctions.get(&auction.product_id) {
+            Some(last) if last.sequence > auction.sequence => {}
+            _ => {
+                self.auctions
+                    .insert(auction.product_id.clone(), auction.clone());
+            }
+        }
+    }
+
+    fn set_auction_mode(&mut self, product_id: &str, auction_mode: bool) {
+        if auction_mode {
+            self.in_auction.insert(product_id.to_string());
+        } else {
+            self.in_auction.remove(product_id);
+            self.auctions.remove(product_id);
+        }
+    }
+
+    pub fn is_in_auction(&self, product_id: &str) -> bool {
+        self.in_auction.contains(product_id)
+    }
+
+    /// Latest auction message of the product
+    pub fn auction(&self, product_id: &str) -> Option<&Auction> {
+        self.auctions.get(product_id)
+    }
+
+    /// Indicative open price and size
+    pub fn indicative_open(&self, product_id: &str) -> Option<(f64, f64)> {
+        let auction = self.auction(product_id)?;
+        Some((auction.open_price?, auction.open_size?))
+    }
+
+    /// Auction has crossing orders and can be opened at indicative price
+    pub fn can_open(&self, product_id: &str) -> bool {
+        self.auction(product_id)
+            .map(|x| x.can_open)
+            .unwrap_or(false)
+    }
+
+    /// Products in auction mode, sorted
+    pub fn products(&self) -> Vec<&str> {
+        let mut products: Vec<_> = self.in_auction.iter().map(|x| x.as_str()).collect();
+        products.sort_unstable();
+        products
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn auction(sequence: usize, open_price: f64) -> Message {
+        let json = format!(
+            r#"{{"type":"auction","product_id":"LTC-USD","sequence":{},"auction_state":"collection","best_bid_price":"333.98","best_bid_size":"4.3","best_ask_price":"333.99","best_ask_size":"25.2","open_price":"{}","open_size":"0.193","can_open":"yes","timestamp":"1547096186.718"}}"#,
+            sequence, open_price
+        );
+        Message::from_json(&json).unwrap()
+    }
+
+    #[test]
+    fn test_tracker() {
+        let mut tracker = AuctionTracker::new();
+        tracker.push_message(&auction(2, 334.0));
+        tracker.push_message(&auction(1, 333.0));
+        assert!(tracker.is_in_auction("LTC-USD"));
+        assert!(tracker.can_open("LTC-USD"));
+        assert_eq!(Some((334.0, 0.193)), tracker.indicative_open("LTC-USD"));
+        assert_eq!(vec!["LTC-USD"], tracker.products());
+
+        let status = r#"{"type":"status","currencies":[],"products":[{"id":"LTC-USD","base_currency":"LTC","quote_currency":"USD","base_increment":"0.00000001","quote_increment":"0.01","display_name":"LTC/USD","status":"online","status_message":"","min_market_funds":"10","post_only":false,"limit_only":false,"cancel_only":false,"fx_stablecoin":false,"auction_mode":false}]}"#;
+        tracker.push_message(&Message::from_json(status).unwrap());
+        assert!(!tracker.is_in_auction("LTC-USD"));
+        assert_eq!(None, tracker.auction("LTC-USD"));
+    }
+}
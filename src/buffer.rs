@@ -0,0 +1,354 @@
+//! Contains bounded buffer between the websocket reader and a slow consumer
+//!
+//! [`BufferedFeed`] reads the feed in its own task, so the socket is drained even when the
+//! consumer stalls, and keeps at most `capacity` messages. What happens when the buffer is full
+//! is chosen by [`Overflow`]. Counters of queued, dropped and conflated messages are available
+//! from [`BufferedFeed::stats`].
+//!
+//! ```no_run
+//! use coinbase_pro_rs::buffer::{BufferedFeed, Overflow};
+//! use coinbase_pro_rs::{structs::wsfeed::ChannelType, WSFeed, WS_URL};
+//! use futures::StreamExt;
+//!
+//! # async fn run() {
+//! let stream = WSFeed::connect(WS_URL, &["BTC-USD"], &[ChannelType::Ticker])
+//!     .await
+//!     .unwrap();
+//! let mut stream = BufferedFeed::new(stream, 1000, Overflow::ConflateTickers);
+//! while let Some(msg) = stream.next().await {
+//!     println!("{:?} {:?}", msg, stream.stats());
+//! }
+//! # }
+//! ```
+
+use futures::stream::{self, BoxStream, Stream, StreamExt};
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::sync::Notify;
+
+use crate::structs::wsfeed::Message;
+use crate::wsfeed::CBStream;
+use crate::CBError;
+
+/// Policy for a message arriving to the full buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    /// Reader waits for the consumer, the socket is not read meanwhile
+    Block,
+    /// Oldest queued message is dropped
+    DropOldest,
+    /// Heartbeats and pongs are dropped, blocks if the buffer is full of other messages
+    DropNonEssential,
+    /// Queued ticker is replaced by a newer ticker of the same product even if the buffer
+    /// is not full, blocks otherwise
+    ConflateTickers,
+}
+
+/// Counters of the buffer
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BufferStats {
+    /// Messages read from the feed
+    pub received: u64,
+    /// Messages waiting for the consumer
+    pub queued: usize,
+    /// Maximum of `queued` so far
+    pub max_queued: usize,
+    pub dropped: u64,
+    /// Tickers replaced by a newer one
+    pub conflated: u64,
+    /// Times the reader waited for the consumer
+    pub blocked: u64,
+}
+
+type Item = Result<Message, CBError>;
+
+struct Queue {
+    items: VecDeque<Item>,
+    stats: BufferStats,
+    /// Feed ended
+    done: bool,
+    /// Consumer dropped
+    closed: bool,
+}
+
+struct Shared {
+    queue: Mutex<Queue>,
+    capacity: usize,
+    overflow: Overflow,
+    item: Notify,
+    space: Notify,
+}
+
+/// Feed read by a background task into a bounded buffer
+pub struct BufferedFeed {
+    shared: Arc<Shared>,
+    inner: BoxStream<'static, Item>,
+}
+
+impl BufferedFeed {
+    /// Spawns the task reading `stream` into the buffer of `capacity` messages
+    pub fn new<S>(stream: S, capacity: usize, overflow: Overflow) -> Self
+    where
+        S: CBStream + 'static,
+    {
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue {
+                items: VecDeque::with_capacity(capacity),
+                stats: BufferStats::default(),
+                done: false,
+                closed: false,
+            }),
+            capacity: capacity.max(1),
+            overflow,
+            item: Notify::new(),
+            space: Notify::new(),
+        });
+        tokio::spawn(read(stream, shared.clone()));
+        let inner = stream::unfold(shared.clone(), |shared| async move {
+            let item = shared.pop().await?;
+            Some((item, shared))
+        })
+        .boxed();
+        BufferedFeed { shared, inner }
+    }
+
+    pub fn stats(&self) -> BufferStats {
+        self.shared.queue.lock().unwrap().stats.clone()
+    }
+}
+
+impl Stream for BufferedFeed {
+    type Item = Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Item>> {
+        self.inner.poll_next_unpin(cx)
+    }
+}
+
+impl Drop for BufferedFeed {
+    fn drop(&mut self) {
+        self.shared.queue.lock().unwrap().closed = true;
+        self.shared.space.notify_one();
+    }
+}
+
+impl Shared {
+    async fn pop(&self) -> Option<Item> {
+        loop {
+            {
+                let mut queue = self.queue.lock().unwrap();
+                if let Some(item) = queue.items.pop_front() {
+                    queue.stats.queued = queue.items.len();
+                    self.space.notify_one();
+                    return Some(item);
+                }
+                if queue.done {
+                    return None;
+                }
+            }
+            self.item.notified().await;
+        }
+    }
+
+    /// Returns the item back if the reader has to wait
+    fn push(&self, queue: &mut Queue, item: Item) -> Option<Item> {
+        if self.overflow == Overflow::ConflateTickers {
+            if let Ok(Message::Ticker(ticker)) = &item {
+                let queued = queue.items.iter_mut().find(|x| match x {
+                    Ok(Message::Ticker(x)) => x.product_id() == ticker.product_id(),
+                    _ => false,
+                });
+                if let Some(queued) = queued {
+                    *queued = item;
+                    queue.stats.conflated += 1;
+                    return None;
+                }
+            }
+        }
+        if queue.items.len() >= self.capacity {
+            match self.overflow {
+                Overflow::Block | Overflow::ConflateTickers => return Some(item),
+                Overflow::DropOldest => {
+                    queue.items.pop_front();
+                    queue.stats.dropped += 1;
+                }
+                Overflow::DropNonEssential => {
+                    if !is_essential(&item) {
+                        queue.stats.dropped += 1;
+                        return None;
+                    }
+                    match queue.items.iter().position(|x| !is_essential(x)) {
+                        Some(pos) => {
+                            queue.items.remove(pos);
+                            queue.stats.dropped += 1;
+                        }
+                        None => return Some(item),
+                    }
+                }
+            }
+        }
+        queue.items.push_back(item);
+        queue.stats.queued = queue.items.len();
+        queue.stats.max_queued = queue.stats.max_queued.max(queue.stats.queued);
+        None
+    }
+}
+
+fn is_essential(item: &Item) -> bool {
+    !matches!(
+        item,
+        Ok(Message::Heartbeat { .. }) | Ok(Message::Pong { .. })
+    )
+}
+
+async fn read<S: CBStream>(mut stream: S, shared: Arc<Shared>) {
+    while let Some(item) = stream.next().await {
+        let mut item = Some(item);
+        let mut counted = false;
+        while let Some(x) = item.take() {
+            {
+                let mut queue = shared.queue.lock().unwrap();
+                if queue.closed {
+                    return;
+                }
+                if !counted {
+                    queue.stats.received += 1;
+                    counted = true;
+                }
+                item = shared.push(&mut queue, x);
+                if item.is_some() {
+                    queue.stats.blocked += 1;
+                }
+            }
+            shared.item.notify_one();
+            if item.is_some() {
+                shared.space.notified().await;
+            }
+        }
+    }
+    shared.queue.lock().unwrap().done = true;
+    shared.item.notify_one();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::wsfeed::Ticker;
+    use futures::{channel::oneshot, future};
+
+    fn ticker(product_id: &str, sequence: usize) -> Message {
+        Message::Ticker(Ticker::Empty {
+            sequence,
+            product_id: product_id.to_string(),
+            price: 1.0,
+        })
+    }
+
+    fn heartbeat(sequence: usize) -> Message {
+        Message::Heartbeat {
+            sequence,
+            last_trade_id: 1,
+            product_id: "BTC-USD".to_string(),
+            time: "2019-08-21T22:10:15.190000Z".parse().unwrap(),
+        }
+    }
+
+    /// Feed pausing after the first `at` items: `reached` resolves once the reader has handled
+    /// them, the rest is read when `release` is sent or dropped
+    fn gated(
+        mut items: Vec<Message>,
+        at: usize,
+    ) -> (
+        BoxStream<'static, Item>,
+        oneshot::Receiver<()>,
+        oneshot::Sender<()>,
+    ) {
+        let rest = items.split_off(at);
+        let (reached_tx, reached) = oneshot::channel();
+        let (release, release_rx) = oneshot::channel::<()>();
+        let gate = stream::once(async move {
+            let _ = reached_tx.send(());
+            let _ = release_rx.await;
+        })
+        .filter_map(|_| future::ready(None));
+        let stream = stream::iter(items)
+            .chain(gate)
+            .chain(stream::iter(rest))
+            .map(Ok)
+            .boxed();
+        (stream, reached, release)
+    }
+
+    async fn buffered(items: Vec<Message>, capacity: usize, overflow: Overflow) -> BufferedFeed {
+        let at = items.len();
+        let (stream, reached, _) = gated(items, at);
+        let feed = BufferedFeed::new(stream, capacity, overflow);
+        reached.await.unwrap();
+        feed
+    }
+
+    #[tokio::test]
+    async fn test_block() {
+        let items: Vec<_> = (0..5).map(|x| ticker("BTC-USD", x)).collect();
+        let (stream, reached, release) = gated(items, 3);
+        let mut feed = BufferedFeed::new(stream, 2, Overflow::Block);
+        // third ticker waits until the first one is taken
+        let mut res = vec![feed.next().await.unwrap().unwrap()];
+        reached.await.unwrap();
+        let stats = feed.stats();
+        assert_eq!((3, 2, 1), (stats.received, stats.queued, stats.blocked));
+
+        release.send(()).unwrap();
+        while let Some(x) = feed.next().await {
+            res.push(x.unwrap());
+        }
+        assert_eq!(
+            (0..5).map(|x| ticker("BTC-USD", x)).collect::<Vec<_>>(),
+            res
+        );
+        assert_eq!(0, feed.stats().dropped);
+    }
+
+    #[tokio::test]
+    async fn test_drop_oldest() {
+        let items: Vec<_> = (0..5).map(|x| ticker("BTC-USD", x)).collect();
+        let feed = buffered(items, 2, Overflow::DropOldest).await;
+        assert_eq!(3, feed.stats().dropped);
+        let res: Vec<_> = feed.map(Result::unwrap).collect().await;
+        assert_eq!(vec![ticker("BTC-USD", 3), ticker("BTC-USD", 4)], res);
+    }
+
+    #[tokio::test]
+    async fn test_drop_non_essential() {
+        let items = vec![
+            heartbeat(1),
+            ticker("BTC-USD", 2),
+            ticker("BTC-USD", 3),
+            heartbeat(4),
+        ];
+        let feed = buffered(items, 2, Overflow::DropNonEssential).await;
+        let res: Vec<_> = feed.map(Result::unwrap).collect().await;
+        assert_eq!(vec![ticker("BTC-USD", 2), ticker("BTC-USD", 3)], res);
+    }
+
+    #[tokio::test]
+    async fn test_conflate_tickers() {
+        let items = vec![
+            ticker("BTC-USD", 1),
+            ticker("ETH-USD", 2),
+            ticker("BTC-USD", 3),
+            heartbeat(4),
+            ticker("BTC-USD", 5),
+        ];
+        let feed = buffered(items, 10, Overflow::ConflateTickers).await;
+        let stats = feed.stats();
+        assert_eq!((5, 3, 2), (stats.received, stats.queued, stats.conflated));
+        let res: Vec<_> = feed.map(Result::unwrap).collect().await;
+        assert_eq!(
+            vec![ticker("BTC-USD", 5), ticker("ETH-USD", 2), heartbeat(4)],
+            res
+        );
+    }
+}
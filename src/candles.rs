@@ -0,0 +1,277 @@
+//! Contains aggregation of trades into candles of arbitrary duration
+//!
+//! Coinbase provides only fixed `Granularity` values and no live candles.
+//! [`CandleAggregator`] builds OHLCV bars of any duration (10 seconds, 2 hours, a week, ...) from
+//! websocket `Match` messages or REST `Trade` records, [`resample`] converts existing candles to
+//! coarser interval.
+//!
+//! Buckets are aligned to unix epoch unless other origin is set, eg: Monday for weekly candles.
+//! Interval should be a positive whole number of seconds, like candles of Coinbase.
+
+use chrono::{Duration, TimeZone, Utc};
+use thiserror::Error;
+
+use crate::structs::public::{Candle, Trade};
+use crate::structs::wsfeed::{Full, Match, Message};
+use crate::structs::DateTime;
+
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error("candle interval should be a positive whole number of seconds: {0}")]
+pub struct InvalidIntervalError(Duration);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Buckets {
+    interval: i64,
+    origin: i64,
+}
+
+impl Buckets {
+    fn new(interval: Duration, origin: Option<DateTime>) -> Result<Self, InvalidIntervalError> {
+        let secs = interval.num_seconds();
+        if secs <= 0 || Duration::seconds(secs) != interval {
+            return Err(InvalidIntervalError(interval));
+        }
+        Ok(Buckets {
+            interval: secs,
+            origin: origin.map(|x| x.timestamp()).unwrap_or(0),
+        })
+    }
+
+    fn start(&self, time: DateTime) -> DateTime {
+        let time = time.timestamp();
+        let start = self.origin + (time - self.origin).div_euclid(self.interval) * self.interval;
+        Utc.timestamp_opt(start, 0).unwrap()
+    }
+}
+
+/// Incremental builder of candles from trades
+///
+/// Trades should be pushed in time order, trades older than in-progress candle are ignored.
+/// Buckets without trades are not emitted. One aggregator handles one product.
+#[derive(Debug, Clone)]
+pub struct CandleAggregator {
+    buckets: Buckets,
+    current: Option<Candle>,
+}
+
+impl CandleAggregator {
+    pub fn new(interval: Duration) -> Result<Self, InvalidIntervalError> {
+        Ok(CandleAggregator {
+            buckets: Buckets::new(interval, None)?,
+            current: None,
+        })
+    }
+
+    /// Aligns buckets to `origin` instead of unix epoch
+    pub fn origin(mut self, origin: DateTime) -> Self {
+        self.buckets.origin = origin.timestamp();
+        self
+    }
+
+    /// Adds trade, returns previous candle when the trade starts a new one
+    pub fn push(&mut self, time: DateTime, price: f64, size: f64) -> Option<Candle> {
+        let start = self.buckets.start(time);
+        match &mut self.current {
+            Some(candle) if candle.time == start => {
+                candle.low = candle.low.min(price);
+                candle.high = candle.high.max(price);
+                candle.close = price;
+                candle.volume += size;
+                None
+            }
+            Some(candle) if candle.time > start => None,
+            _ => self
+                .current
+                .replace(Candle::new(start, price, price, price, price, size)),
+        }
+    }
+
+    pub fn push_match(&mut self, m: &Match) -> Option<Candle> {
+        self.push(m.time, m.price, m.size)
+    }
+
+    pub fn push_trade(&mut self, trade: &Trade) -> Option<Candle> {
+        self.push(trade.time, trade.price, trade.size)
+    }
+
+    /// Adds `Message::Match` (matches channel) or `Full::Match` (full channel), skips others
+    pub fn push_message(&mut self, msg: &Message) -> Option<Candle> {
+        match msg {
+            Message::Match(m) | Message::Full(Full::Match(m)) => self.push_match(m),
+            _ => None,
+        }
+    }
+
+    /// In-progress candle
+    pub fn current(&self) -> Option<&Candle> {
+        self.current.as_ref()
+    }
+
+    /// Finishes in-progress candle
+    pub fn flush(&mut self) -> Option<Candle> {
+        self.current.take()
+    }
+}
+
+/// Builds candles from trades in any order, eg: result of `get_trades` which is newest first
+pub fn aggregate_trades(
+    trades: &[Trade],
+    interval: Duration,
+) -> Result<Vec<Candle>, InvalidIntervalError> {
+    let mut aggregator = CandleAggregator::new(interval)?;
+    let mut trades: Vec<_> = trades.iter().collect();
+    trades.sort_by_key(|x| x.trade_id);
+
+    let mut candles: Vec<_> = trades
+        .into_iter()
+        .filter_map(|x| aggregator.push_trade(x))
+        .collect();
+    candles.extend(aggregator.flush());
+    Ok(candles)
+}
+
+/// Converts candles to coarser interval which should be multiple of their granularity.
+/// Result is sorted by time, oldest first
+pub fn resample(
+    candles: &[Candle],
+    interval: Duration,
+    origin: Option<DateTime>,
+) -> Result<Vec<Candle>, InvalidIntervalError> {
+    let buckets = Buckets::new(interval, origin)?;
+    let mut candles: Vec<_> = candles.iter().collect();
+    candles.sort_by_key(|x| x.time);
+
+    let mut res: Vec<Candle> = vec![];
+    for candle in candles {
+        let start = buckets.start(candle.time);
+        match res.last_mut() {
+            Some(last) if last.time == start => {
+                last.low = last.low.min(candle.low);
+                last.high = last.high.max(candle.high);
+                last.close = candle.close;
+                last.volume += candle.volume;
+            }
+            _ => res.push(Candle {
+                time: start,
+                ..*candle
+            }),
+        }
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::reqs::OrderSide;
+
+    fn time(s: &str) -> DateTime {
+        s.parse().unwrap()
+    }
+
+    fn candle(time: i64, open: f64, high: f64, low: f64, close: f64, volume: f64) -> Candle {
+        let time = Utc.timestamp_opt(time, 0).unwrap();
+        Candle::new(time, open, high, low, close, volume)
+    }
+
+    fn trade(trade_id: usize, t: &str, price: f64, size: f64) -> Trade {
+        Trade {
+            time: time(t),
+            trade_id,
+            price,
+            size,
+            side: OrderSide::Buy,
+        }
+    }
+
+    #[test]
+    fn test_aggregator() {
+        let mut aggregator = CandleAggregator::new(Duration::seconds(10)).unwrap();
+        assert_eq!(
+            None,
+            aggregator.push(time("2020-01-01T00:00:01Z"), 10.0, 1.0)
+        );
+        assert_eq!(
+            None,
+            aggregator.push(time("2020-01-01T00:00:05Z"), 12.0, 1.0)
+        );
+        assert_eq!(
+            None,
+            aggregator.push(time("2020-01-01T00:00:09Z"), 9.0, 0.5)
+        );
+        assert_eq!(
+            Some(&candle(1577836800, 10.0, 12.0, 9.0, 9.0, 2.5)),
+            aggregator.current()
+        );
+
+        let prev = aggregator.push(time("2020-01-01T00:00:31Z"), 11.0, 1.0);
+        assert_eq!(Some(candle(1577836800, 10.0, 12.0, 9.0, 9.0, 2.5)), prev);
+        // late trade
+        assert_eq!(
+            None,
+            aggregator.push(time("2020-01-01T00:00:09Z"), 1.0, 1.0)
+        );
+        assert_eq!(
+            Some(candle(1577836830, 11.0, 11.0, 11.0, 11.0, 1.0)),
+            aggregator.flush()
+        );
+        assert_eq!(None, aggregator.current());
+    }
+
+    #[test]
+    fn test_invalid_interval() {
+        assert!(CandleAggregator::new(Duration::milliseconds(500)).is_err());
+        assert!(CandleAggregator::new(Duration::milliseconds(1500)).is_err());
+        assert!(CandleAggregator::new(Duration::zero()).is_err());
+        assert!(resample(&[], Duration::seconds(-60), None).is_err());
+    }
+
+    #[test]
+    fn test_aggregator_weekly() {
+        let monday = time("2020-01-06T00:00:00Z");
+        let mut aggregator = CandleAggregator::new(Duration::weeks(1))
+            .unwrap()
+            .origin(monday);
+        aggregator.push(time("2020-01-15T13:00:00Z"), 10.0, 1.0);
+        assert_eq!(
+            time("2020-01-13T00:00:00Z"),
+            aggregator.current().unwrap().time
+        );
+    }
+
+    #[test]
+    fn test_aggregate_trades() {
+        let trades = vec![
+            trade(4, "2020-01-01T02:10:00Z", 13.0, 1.0),
+            trade(3, "2020-01-01T01:59:00Z", 12.0, 1.0),
+            trade(2, "2020-01-01T01:00:00Z", 8.0, 2.0),
+            trade(1, "2020-01-01T00:30:00Z", 10.0, 1.0),
+        ];
+        let candles = aggregate_trades(&trades, Duration::hours(2)).unwrap();
+        assert_eq!(
+            vec![
+                candle(1577836800, 10.0, 12.0, 8.0, 12.0, 4.0),
+                candle(1577844000, 13.0, 13.0, 13.0, 13.0, 1.0),
+            ],
+            candles
+        );
+    }
+
+    #[test]
+    fn test_resample() {
+        let candles = vec![
+            candle(1577836920, 10.0, 11.0, 9.0, 10.5, 1.0),
+            candle(1577836800, 11.0, 12.0, 10.0, 10.0, 2.0),
+            candle(1577836860, 10.0, 10.0, 8.0, 9.0, 3.0),
+            candle(1577837100, 10.0, 10.0, 10.0, 10.0, 1.0),
+        ];
+        let resampled = resample(&candles, Duration::minutes(5), None).unwrap();
+        assert_eq!(
+            vec![
+                candle(1577836800, 11.0, 12.0, 8.0, 10.5, 6.0),
+                candle(1577837100, 10.0, 10.0, 10.0, 10.0, 1.0),
+            ],
+            resampled
+        );
+    }
+}
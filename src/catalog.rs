@@ -0,0 +1,380 @@
+//! Contains cache of products and currencies with change notifications
+//!
+//! [`ProductCatalog`] loads `Product`/`Currency` lists once, then keeps them up to date from
+//! periodic REST refresh ([`ProductCatalog::spawn_refresh`]) and from the websocket `status`
+//! channel ([`ProductCatalog::push_message`]). Trading status and increment changes are
+//! broadcast to [`subscribe`](ProductCatalog::subscribe)rs as [`ProductEvent`].
+//!
+//! ```no_run
+//! use coinbase_pro_rs::{catalog::ProductCatalog, ASync, Public, MAIN_URL};
+//! use std::{sync::Arc, time::Duration};
+//!
+//! # async fn run() {
+//! let client: Arc<Public<ASync>> = Arc::new(Public::new(MAIN_URL));
+//! let catalog = ProductCatalog::load(client.as_ref()).await.unwrap();
+//! let mut events = catalog.subscribe();
+//! catalog.clone().spawn_refresh(client, Duration::from_secs(60));
+//!
+//! let tick = catalog.product("BTC-USD").unwrap().quote_increment;
+//! while let Ok(event) = events.recv().await {
+//!     println!("{:?}", event);
+//! }
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+use crate::api::PublicApi;
+use crate::structs::public::{Currency, Product, ProductStatus};
+use crate::structs::wsfeed::{Message, StatusCurrency, StatusProduct};
+use crate::CBError;
+
+const EVENTS_CAPACITY: usize = 1024;
+
+/// Change of one product
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProductChange {
+    /// New product
+    Listed,
+    /// Product got `delisted` status or disappeared from the list
+    Delisted,
+    Status {
+        old: ProductStatus,
+        new: ProductStatus,
+    },
+    CancelOnly(bool),
+    PostOnly(bool),
+    LimitOnly(bool),
+    TradingDisabled(bool),
+    Increments {
+        base_increment: f64,
+        quote_increment: f64,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProductEvent {
+    pub product_id: String,
+    pub change: ProductChange,
+}
+
+#[derive(Default)]
+struct Inner {
+    products: HashMap<String, Product>,
+    currencies: HashMap<String, Currency>,
+}
+
+/// Shared cache of products and currencies, clones share the same data
+#[derive(Clone)]
+pub struct ProductCatalog {
+    inner: Arc<RwLock<Inner>>,
+    events: broadcast::Sender<ProductEvent>,
+}
+
+impl Default for ProductCatalog {
+    fn default() -> Self {
+        let (events, _) = broadcast::channel(EVENTS_CAPACITY);
+        ProductCatalog {
+            inner: Arc::default(),
+            events,
+        }
+    }
+}
+
+impl ProductCatalog {
+    /// Empty catalog, filled by `refresh` or `status` messages
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Catalog loaded from `get_products` and `get_currencies`
+    pub async fn load(api: &impl PublicApi) -> Result<Self, CBError> {
+        let catalog = Self::new();
+        catalog.refresh(api).await?;
+        Ok(catalog)
+    }
+
+    /// Reloads products and currencies, products missing in the new list are removed
+    /// and reported as `Delisted`
+    pub async fn refresh(&self, api: &impl PublicApi) -> Result<(), CBError> {
+        let products = api.get_products().await?;
+        let currencies = api.get_currencies().await?;
+        self.update(products, currencies, true);
+        Ok(())
+    }
+
+    /// Refreshes catalog every `interval` until the task is aborted, errors are logged
+    pub fn spawn_refresh<P>(self, api: Arc<P>, interval: Duration) -> JoinHandle<()>
+    where
+        P: PublicApi + 'static,
+    {
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                if let Err(e) = self.refresh(api.as_ref()).await {
+                    log::warn!("product catalog refresh failed: {}", e);
+                }
+            }
+        })
+    }
+
+    /// Applies `Message::Status` from the websocket `status` channel, skips others
+    pub fn push_message(&self, msg: &Message) {
+        if let Message::Status {
+            products,
+            currencies,
+        } = msg
+        {
+            self.push_status(products, currencies);
+        }
+    }
+
+    pub fn push_status(&self, products: &[StatusProduct], currencies: &[StatusCurrency]) {
+        let products = products.iter().cloned().map(Product::from).collect();
+        let currencies = currencies.iter().cloned().map(Currency::from).collect();
+        self.update(products, currencies, false);
+    }
+
+    /// Receiver of changes made after this call
+    pub fn subscribe(&self) -> broadcast::Receiver<ProductEvent> {
+        self.events.subscribe()
+    }
+
+    pub fn product(&self, product_id: &str) -> Option<Product> {
+        self.inner.read().unwrap().products.get(product_id).cloned()
+    }
+
+    pub fn products(&self) -> Vec<Product> {
+        let mut products: Vec<_> = self
+            .inner
+            .read()
+            .unwrap()
+            .products
+            .values()
+            .cloned()
+            .collect();
+        products.sort_by(|a, b| a.id.cmp(&b.id));
+        products
+    }
+
+    pub fn currency(&self, currency_id: &str) -> Option<Currency> {
+        self.inner
+            .read()
+            .unwrap()
+            .currencies
+            .get(currency_id)
+            .cloned()
+    }
+
+    pub fn currencies(&self) -> Vec<Currency> {
+        let mut currencies: Vec<_> = self
+            .inner
+            .read()
+            .unwrap()
+            .currencies
+            .values()
+            .cloned()
+            .collect();
+        currencies.sort_by(|a, b| a.id.cmp(&b.id));
+        currencies
+    }
+
+    fn update(&self, products: Vec<Product>, currencies: Vec<Currency>, full: bool) {
+        let mut events = vec![];
+        {
+            let mut inner = self.inner.write().unwrap();
+            if full {
+                inner.products.retain(|id, _| {
+                    let keep = products.iter().any(|x| &x.id == id);
+                    if !keep {
+                        events.push(ProductEvent {
+                            product_id: id.clone(),
+                            change: ProductChange::Delisted,
+                        });
+                    }
+                    keep
+                });
+                inner.currencies.clear();
+            }
+            for product in products {
+                let changes = changes(inner.products.get(&product.id), &product);
+                events.extend(changes.into_iter().map(|change| ProductEvent {
+                    product_id: product.id.clone(),
+                    change,
+                }));
+                inner.products.insert(product.id.clone(), product);
+            }
+            for currency in currencies {
+                inner.currencies.insert(currency.id.clone(), currency);
+            }
+        }
+        for event in events {
+            // no subscribers is not an error
+            let _ = self.events.send(event);
+        }
+    }
+}
+
+fn changes(old: Option<&Product>, new: &Product) -> Vec<ProductChange> {
+    let old = match old {
+        Some(old) => old,
+        None => return vec![ProductChange::Listed],
+    };
+    let mut res = vec![];
+    if old.status != new.status {
+        res.push(if new.status == ProductStatus::Delisted {
+            ProductChange::Delisted
+        } else {
+            ProductChange::Status {
+                old: old.status,
+                new: new.status,
+            }
+        });
+    }
+    if old.cancel_only != new.cancel_only {
+        res.push(ProductChange::CancelOnly(new.cancel_only));
+    }
+    if old.post_only != new.post_only {
+        res.push(ProductChange::PostOnly(new.post_only));
+    }
+    if old.limit_only != new.limit_only {
+        res.push(ProductChange::LimitOnly(new.limit_only));
+    }
+    if old.trading_disabled != new.trading_disabled {
+        res.push(ProductChange::TradingDisabled(new.trading_disabled));
+    }
+    if old.base_increment != new.base_increment || old.quote_increment != new.quote_increment {
+        res.push(ProductChange::Increments {
+            base_increment: new.base_increment,
+            quote_increment: new.quote_increment,
+        });
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn product(id: &str) -> Product {
+        Product {
+            id: id.to_string(),
+            display_name: id.replace('-', "/"),
+            base_currency: id[..3].to_string(),
+            quote_currency: id[4..].to_string(),
+            quote_increment: 0.01,
+            base_increment: 0.00000001,
+            min_market_funds: 1.0,
+            margin_enabled: false,
+            status: ProductStatus::Online,
+            status_message: "".to_string(),
+            cancel_only: false,
+            limit_only: false,
+            post_only: false,
+            trading_disabled: false,
+            fx_stablecoin: false,
+            max_slippage_percentage: 0.1,
+            auction_mode: false,
+        }
+    }
+
+    fn events(rx: &mut broadcast::Receiver<ProductEvent>) -> Vec<ProductEvent> {
+        std::iter::from_fn(|| rx.try_recv().ok()).collect()
+    }
+
+    #[test]
+    fn test_update() {
+        let catalog = ProductCatalog::new();
+        let mut rx = catalog.subscribe();
+        catalog.update(vec![product("BTC-USD"), product("ETH-USD")], vec![], true);
+        assert_eq!(2, events(&mut rx).len());
+
+        let mut btc = product("BTC-USD");
+        btc.post_only = true;
+        btc.quote_increment = 0.1;
+        catalog.update(vec![btc], vec![], true);
+        assert_eq!(
+            vec![
+                ProductEvent {
+                    product_id: "ETH-USD".to_string(),
+                    change: ProductChange::Delisted,
+                },
+                ProductEvent {
+                    product_id: "BTC-USD".to_string(),
+                    change: ProductChange::PostOnly(true),
+                },
+                ProductEvent {
+                    product_id: "BTC-USD".to_string(),
+                    change: ProductChange::Increments {
+                        base_increment: 0.00000001,
+                        quote_increment: 0.1,
+                    },
+                },
+            ],
+            events(&mut rx)
+        );
+        assert_eq!(0.1, catalog.product("BTC-USD").unwrap().quote_increment);
+        assert_eq!(None, catalog.product("ETH-USD"));
+    }
+
+    #[test]
+    fn test_push_status() {
+        let catalog = ProductCatalog::new();
+        catalog.update(vec![product("BTC-USD")], vec![], true);
+        let mut rx = catalog.subscribe();
+
+        let status = StatusProduct {
+            id: "BTC-USD".to_string(),
+            base_currency: "BTC".to_string(),
+            quote_currency: "USD".to_string(),
+            base_increment: 0.00000001,
+            quote_increment: 0.01,
+            display_name: "BTC/USD".to_string(),
+            status: ProductStatus::Delisted,
+            status_message: "".to_string(),
+            min_market_funds: 1.0,
+            post_only: false,
+            limit_only: false,
+            cancel_only: true,
+            fx_stablecoin: false,
+            margin_enabled: false,
+            trading_disabled: false,
+            max_slippage_percentage: 0.1,
+            auction_mode: false,
+        };
+        catalog.push_message(&Message::Status {
+            products: vec![status],
+            currencies: vec![],
+        });
+        let changes: Vec<_> = events(&mut rx).into_iter().map(|x| x.change).collect();
+        assert_eq!(
+            vec![ProductChange::Delisted, ProductChange::CancelOnly(true)],
+            changes
+        );
+        let btc = catalog.product("BTC-USD").unwrap();
+        assert_eq!(ProductStatus::Delisted, btc.status);
+        assert_eq!(0.1, btc.max_slippage_percentage);
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_load() {
+        use crate::{mock::MockServer, ASync, Public};
+        use hyper::Method;
+
+        let server = MockServer::start();
+        server.mock_json(
+            Method::GET,
+            "/products",
+            &serde_json::to_string(&[product("BTC-USD")]).unwrap(),
+        );
+        server.mock_json(Method::GET, "/currencies", "[]");
+        let client: Public<ASync> = Public::new(&server.uri());
+        let catalog = ProductCatalog::load(&client).await.unwrap();
+        assert_eq!(vec![product("BTC-USD")], catalog.products());
+    }
+}
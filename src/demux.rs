@@ -0,0 +1,295 @@
+//! Contains fan-out of one websocket feed into typed per-channel streams
+//!
+//! [`Demux`] reads the feed in its own task and routes every message to the streams interested
+//! in it: [`tickers`](Demux::tickers), [`level2`](Demux::level2), [`matches`](Demux::matches),
+//! [`full`](Demux::full), [`auctions`](Demux::auctions) and [`rfq_matches`](Demux::rfq_matches),
+//! each optionally limited to one product. The rest (subscriptions, heartbeats, status, errors)
+//! goes to the single [`others`](Demux::others) stream. Streams are created before the feed is
+//! [`start`](Demux::start)ed, so they don't miss the first messages. Every stream has its own
+//! buffer of `capacity` messages, a message for a full buffer is dropped and counted in
+//! [`dropped`](Demux::dropped), so a slow consumer does not hold back the others. Streams end when
+//! the feed ends.
+//!
+//! ```no_run
+//! use coinbase_pro_rs::{demux::Demux, structs::wsfeed::ChannelType, WSFeed, WS_URL};
+//! use futures::StreamExt;
+//!
+//! # async fn run() {
+//! let channels = [ChannelType::Ticker, ChannelType::Matches];
+//! let stream = WSFeed::connect(WS_URL, &["BTC-USD", "ETH-USD"], &channels)
+//!     .await
+//!     .unwrap();
+//! let demux = Demux::new(1000);
+//! let mut btc = demux.tickers(Some("BTC-USD"));
+//! let mut matches = demux.matches(None);
+//! demux.start(stream);
+//!
+//! while let Some(ticker) = btc.next().await {
+//!     println!("{}", ticker.price());
+//! }
+//! # }
+//! ```
+
+use futures::channel::mpsc::{self, Receiver, Sender};
+use futures::{Stream, StreamExt};
+use std::sync::{Arc, Mutex};
+
+use crate::structs::wsfeed::{Auction, Full, Level2, Match, Message, RfqMatch, Ticker};
+use crate::wsfeed::CBStream;
+use crate::CBError;
+
+struct Route<T> {
+    product_id: Option<String>,
+    tx: Sender<T>,
+}
+
+#[derive(Default)]
+struct Routes {
+    tickers: Vec<Route<Ticker>>,
+    level2: Vec<Route<Level2>>,
+    matches: Vec<Route<Match>>,
+    full: Vec<Route<Full>>,
+    auctions: Vec<Route<Auction>>,
+    rfq_matches: Vec<Route<RfqMatch>>,
+    others: Option<Sender<Result<Message, CBError>>>,
+    /// Messages dropped because the buffer of their stream was full
+    dropped: u64,
+    started: bool,
+    /// Feed ended, new streams end immediately
+    closed: bool,
+}
+
+impl Routes {
+    fn is_empty(&self) -> bool {
+        self.tickers.is_empty()
+            && self.level2.is_empty()
+            && self.matches.is_empty()
+            && self.full.is_empty()
+            && self.auctions.is_empty()
+            && self.rfq_matches.is_empty()
+            && self.others.is_none()
+    }
+}
+
+/// Router of one feed into typed streams, clones share the same feed
+#[derive(Clone)]
+pub struct Demux {
+    routes: Arc<Mutex<Routes>>,
+    capacity: usize,
+}
+
+impl Demux {
+    /// Demux with buffers of `capacity` messages per stream, the feed is read after
+    /// [`start`](Demux::start)
+    pub fn new(capacity: usize) -> Self {
+        Demux {
+            routes: Arc::default(),
+            capacity,
+        }
+    }
+
+    /// Spawns the task reading `stream`. Streams created later miss the messages received
+    /// before them.
+    ///
+    /// Panics if the demux is already started
+    pub fn start<S>(&self, stream: S)
+    where
+        S: CBStream + 'static,
+    {
+        let mut routes = self.routes.lock().unwrap();
+        assert!(!routes.started, "demux is already started");
+        routes.started = true;
+        tokio::spawn(run(stream, self.routes.clone()));
+    }
+
+    /// Messages dropped so far because the buffer of their stream was full
+    pub fn dropped(&self) -> u64 {
+        self.routes.lock().unwrap().dropped
+    }
+
+    pub fn tickers(&self, product_id: Option<&str>) -> impl Stream<Item = Ticker> + Unpin + Send {
+        self.route(|x| &mut x.tickers, product_id)
+    }
+
+    pub fn level2(&self, product_id: Option<&str>) -> impl Stream<Item = Level2> + Unpin + Send {
+        self.route(|x| &mut x.level2, product_id)
+    }
+
+    /// Messages of the `matches` channel
+    pub fn matches(&self, product_id: Option<&str>) -> impl Stream<Item = Match> + Unpin + Send {
+        self.route(|x| &mut x.matches, product_id)
+    }
+
+    pub fn full(&self, product_id: Option<&str>) -> impl Stream<Item = Full> + Unpin + Send {
+        self.route(|x| &mut x.full, product_id)
+    }
+
+    pub fn auctions(&self, product_id: Option<&str>) -> impl Stream<Item = Auction> + Unpin + Send {
+        self.route(|x| &mut x.auctions, product_id)
+    }
+
+    pub fn rfq_matches(
+        &self,
+        product_id: Option<&str>,
+    ) -> impl Stream<Item = RfqMatch> + Unpin + Send {
+        self.route(|x| &mut x.rfq_matches, product_id)
+    }
+
+    /// Messages without typed stream and errors of the feed. Errors can't be cloned,
+    /// so there is only one such stream: the previous one ends when a new one is created
+    pub fn others(&self) -> impl Stream<Item = Result<Message, CBError>> + Unpin + Send {
+        let (tx, rx) = mpsc::channel(self.capacity);
+        let mut routes = self.routes.lock().unwrap();
+        if !routes.closed {
+            routes.others = Some(tx);
+        }
+        rx
+    }
+
+    fn route<T>(
+        &self,
+        routes: fn(&mut Routes) -> &mut Vec<Route<T>>,
+        product_id: Option<&str>,
+    ) -> Receiver<T> {
+        let (tx, rx) = mpsc::channel(self.capacity);
+        let mut guard = self.routes.lock().unwrap();
+        if !guard.closed {
+            routes(&mut guard).push(Route {
+                product_id: product_id.map(|x| x.to_string()),
+                tx,
+            });
+        }
+        rx
+    }
+}
+
+/// Sends `item` to matching routes, drops routes of closed streams.
+/// Returns number of full buffers the item was dropped for
+fn dispatch<T: Clone>(routes: &mut Vec<Route<T>>, item: T, product_id: fn(&T) -> &str) -> u64 {
+    let id = product_id(&item);
+    let mut dropped = 0;
+    routes.retain_mut(|route| match &route.product_id {
+        Some(x) if x != id => !route.tx.is_closed(),
+        _ => match route.tx.try_send(item.clone()) {
+            Ok(()) => true,
+            Err(e) if e.is_full() => {
+                dropped += 1;
+                true
+            }
+            Err(_) => false,
+        },
+    });
+    dropped
+}
+
+/// Runs until the feed ends or all streams and `Demux` clones are dropped
+async fn run<S>(mut stream: S, routes: Arc<Mutex<Routes>>)
+where
+    S: CBStream,
+{
+    while let Some(msg) = stream.next().await {
+        let mut guard = routes.lock().unwrap();
+        let r = &mut *guard;
+        let dropped = match msg {
+            Ok(Message::Ticker(x)) => dispatch(&mut r.tickers, x, |x| x.product_id()),
+            Ok(Message::Level2(x)) => dispatch(&mut r.level2, x, |x| x.product_id()),
+            Ok(Message::Match(x)) => dispatch(&mut r.matches, x, |x| &x.product_id),
+            Ok(Message::Full(x)) => dispatch(&mut r.full, x, |x| x.product_id()),
+            Ok(Message::Auction(x)) => dispatch(&mut r.auctions, x, |x| &x.product_id),
+            Ok(Message::RfqMatch(x)) => dispatch(&mut r.rfq_matches, x, |x| &x.product_id),
+            msg => match &mut r.others {
+                Some(tx) => match tx.try_send(msg) {
+                    Ok(()) => 0,
+                    Err(e) if e.is_full() => 1,
+                    Err(_) => {
+                        r.others = None;
+                        0
+                    }
+                },
+                None => 0,
+            },
+        };
+        if dropped > 0 {
+            log::warn!("demux stream buffer is full, message dropped");
+            r.dropped += dropped;
+        }
+        if r.is_empty() && Arc::strong_count(&routes) == 1 {
+            log::debug!("demux stopped, no streams left");
+            return;
+        }
+    }
+    // drops senders, so the streams end
+    let mut guard = routes.lock().unwrap();
+    *guard = Routes {
+        dropped: guard.dropped,
+        started: true,
+        closed: true,
+        ..Routes::default()
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream;
+
+    fn ticker(product_id: &str, sequence: usize) -> Ticker {
+        Ticker::Empty {
+            sequence,
+            product_id: product_id.to_string(),
+            price: 1.0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_demux() {
+        let feed = stream::iter(vec![
+            Ok(Message::Ticker(ticker("BTC-USD", 1))),
+            Ok(Message::Ticker(ticker("ETH-USD", 2))),
+            Ok(Message::Error {
+                message: "test".to_string(),
+            }),
+            Ok(Message::Ticker(ticker("BTC-USD", 3))),
+        ]);
+        let demux = Demux::new(10);
+        let all = demux.tickers(None);
+        let btc = demux.tickers(Some("BTC-USD"));
+        let matches = demux.matches(None);
+        let others = demux.others();
+        demux.start(feed);
+
+        assert_eq!(
+            vec![ticker("BTC-USD", 1), ticker("BTC-USD", 3)],
+            btc.collect::<Vec<_>>().await
+        );
+        assert_eq!(3, all.collect::<Vec<_>>().await.len());
+        assert!(matches.collect::<Vec<_>>().await.is_empty());
+        assert_eq!(
+            vec![Ok(Message::Error {
+                message: "test".to_string()
+            })],
+            others.collect::<Vec<_>>().await
+        );
+        assert_eq!(0, demux.dropped());
+    }
+
+    #[tokio::test]
+    async fn test_full_buffer() {
+        let tickers = stream::iter((0..5).map(|x| Message::Ticker(ticker("BTC-USD", x))));
+        // feed waits for `tx` after the tickers, so they are read before the stream is polled
+        let (tx, rx) = futures::channel::oneshot::channel::<()>();
+        let end = stream::once(rx).map(|_| Message::Error {
+            message: "end".to_string(),
+        });
+        let demux = Demux::new(1);
+        let slow = demux.tickers(None);
+        demux.start(tickers.chain(end).map(Ok));
+        tokio::task::yield_now().await;
+        tx.send(()).unwrap();
+
+        let received = slow.collect::<Vec<_>>().await;
+        assert_eq!(ticker("BTC-USD", 0), received[0]);
+        assert!(demux.dropped() > 0);
+        assert_eq!(5, received.len() as u64 + demux.dropped());
+    }
+}
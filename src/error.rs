@@ -30,6 +30,14 @@ pub enum CBError {
     #[error("websocket: {0}")]
     Websocket(WSError),
 
+    /// IO error
+    #[error("io: {0}")]
+    Io(#[source] std::io::Error),
+
+    /// Invalid product id, no request is made
+    #[error("product id: {0}")]
+    ProductId(#[source] crate::structs::public::ParseProductIdError),
+
     /// Null error
     #[error("null")]
     Null,
@@ -49,6 +57,14 @@ impl PartialEq for CBError {
                 }
             }
             CBError::Websocket(_) => false,
+            CBError::Io(_) => false,
+            CBError::ProductId(s) => {
+                if let CBError::ProductId(o) = other {
+                    s == o
+                } else {
+                    false
+                }
+            }
             CBError::Null => true,
         }
     }
@@ -69,6 +85,15 @@ pub enum WSError {
     /// Error reading from websocket
     #[error("read")]
     Read(#[source] tokio_tungstenite::tungstenite::Error),
+
+    /// Connection closed by the server, with reason if the close frame had one
+    #[error("closed{}", .0.as_ref().map(|x| format!(": {}", x)).unwrap_or_default())]
+    Closed(Option<crate::structs::wsfeed::CloseReason>),
+
+    /// No heartbeat of the product received within the watchdog interval, without product
+    /// if no heartbeat product is known yet
+    #[error("stale feed: no heartbeat{} for {1:?}", .0.as_ref().map(|x| format!(" of {}", x)).unwrap_or_default())]
+    Stale(Option<String>, std::time::Duration),
 }
 
 impl<'de> Deserialize<'de> for WSError {
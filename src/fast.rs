@@ -0,0 +1,359 @@
+//! Contains borrowing decoder of the busiest websocket messages
+//!
+//! `Message::from_json` allocates every product id, order id and side, parses the frame into
+//! `InputMessage` and converts it into `Message`. [`decode`] picks the message type from the
+//! frame and deserializes only that type into [`FastMessage`], borrowing strings from the
+//! frame. Decimals are kept as [`Num`] and parsed on demand. Types other than `heartbeat`,
+//! `ticker`, `level2` and `full` channel ones are `FastMessage::Other`, so the caller can fall
+//! back to `Message::from_json`. [`ProductIds`] interns product ids which have to outlive the
+//! frame.
+//!
+//! ```no_run
+//! use coinbase_pro_rs::fast::{decode, FastMessage, ProductIds};
+//! use coinbase_pro_rs::structs::wsfeed::Message;
+//!
+//! # fn run(frame: &str) {
+//! let mut ids = ProductIds::new();
+//! match decode(frame).unwrap() {
+//!     FastMessage::Match(x) => println!("{} {}", ids.intern(x.product_id), x.price.to_f64()),
+//!     FastMessage::Other(_) => println!("{:?}", Message::from_json(frame)),
+//!     _ => (),
+//! }
+//! # }
+//! ```
+
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// Decimal sent as a quoted string, parsed by [`to_f64`](Num::to_f64)
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(transparent)]
+pub struct Num<'a>(pub &'a str);
+
+impl<'a> Num<'a> {
+    pub fn as_str(&self) -> &'a str {
+        self.0
+    }
+
+    /// NaN if the value is not a number, eg: empty `best_bid`
+    pub fn to_f64(&self) -> f64 {
+        self.0.parse().unwrap_or(f64::NAN)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct HeartbeatRef<'a> {
+    pub sequence: usize,
+    pub last_trade_id: usize,
+    pub product_id: &'a str,
+    pub time: &'a str,
+}
+
+/// Both full and empty tickers, fields missing in the empty one are `None`
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct TickerRef<'a> {
+    pub sequence: usize,
+    pub product_id: &'a str,
+    #[serde(borrow)]
+    pub price: Num<'a>,
+    pub trade_id: Option<usize>,
+    #[serde(borrow)]
+    pub time: Option<&'a str>,
+    #[serde(borrow)]
+    pub side: Option<&'a str>,
+    #[serde(borrow)]
+    pub last_size: Option<Num<'a>>,
+    #[serde(borrow)]
+    pub best_bid: Option<Num<'a>>,
+    #[serde(borrow)]
+    pub best_ask: Option<Num<'a>>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct SnapshotRef<'a> {
+    pub product_id: &'a str,
+    /// price, size
+    #[serde(borrow)]
+    pub bids: Vec<(Num<'a>, Num<'a>)>,
+    #[serde(borrow)]
+    pub asks: Vec<(Num<'a>, Num<'a>)>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct L2updateRef<'a> {
+    pub product_id: &'a str,
+    pub time: &'a str,
+    /// side, price, size
+    #[serde(borrow)]
+    pub changes: Vec<(&'a str, Num<'a>, Num<'a>)>,
+}
+
+/// Received limit or market order, `size` and `price` are set for limit ones
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct ReceivedRef<'a> {
+    pub time: &'a str,
+    pub product_id: &'a str,
+    pub sequence: usize,
+    pub order_id: &'a str,
+    pub order_type: &'a str,
+    pub side: &'a str,
+    #[serde(borrow)]
+    pub size: Option<Num<'a>>,
+    #[serde(borrow)]
+    pub price: Option<Num<'a>>,
+    #[serde(borrow)]
+    pub funds: Option<Num<'a>>,
+    #[serde(borrow)]
+    pub client_oid: Option<&'a str>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct OpenRef<'a> {
+    pub time: &'a str,
+    pub product_id: &'a str,
+    pub sequence: usize,
+    pub order_id: &'a str,
+    #[serde(borrow)]
+    pub price: Num<'a>,
+    #[serde(borrow)]
+    pub remaining_size: Num<'a>,
+    pub side: &'a str,
+}
+
+/// Done limit or market order, `price` and `remaining_size` are set for limit ones
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct DoneRef<'a> {
+    pub time: &'a str,
+    pub product_id: &'a str,
+    pub sequence: Option<usize>,
+    pub order_id: &'a str,
+    pub reason: &'a str,
+    pub side: &'a str,
+    #[serde(borrow)]
+    pub price: Option<Num<'a>>,
+    #[serde(borrow)]
+    pub remaining_size: Option<Num<'a>>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct MatchRef<'a> {
+    pub trade_id: usize,
+    pub sequence: usize,
+    pub maker_order_id: &'a str,
+    pub taker_order_id: &'a str,
+    pub time: &'a str,
+    pub product_id: &'a str,
+    #[serde(borrow)]
+    pub size: Num<'a>,
+    #[serde(borrow)]
+    pub price: Num<'a>,
+    pub side: &'a str,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct ChangeRef<'a> {
+    pub time: &'a str,
+    pub product_id: &'a str,
+    pub sequence: usize,
+    pub order_id: &'a str,
+    pub side: &'a str,
+    #[serde(borrow)]
+    pub new_size: Option<Num<'a>>,
+    #[serde(borrow)]
+    pub old_size: Option<Num<'a>>,
+    #[serde(borrow)]
+    pub new_funds: Option<Num<'a>>,
+    #[serde(borrow)]
+    pub old_funds: Option<Num<'a>>,
+    #[serde(borrow)]
+    pub price: Option<Num<'a>>,
+}
+
+/// Message borrowing from the decoded frame
+#[derive(Debug, Clone, PartialEq)]
+pub enum FastMessage<'a> {
+    Heartbeat(HeartbeatRef<'a>),
+    Ticker(TickerRef<'a>),
+    Snapshot(SnapshotRef<'a>),
+    L2update(L2updateRef<'a>),
+    Received(ReceivedRef<'a>),
+    Open(OpenRef<'a>),
+    Done(DoneRef<'a>),
+    /// `match` and `last_match`
+    Match(MatchRef<'a>),
+    Change(ChangeRef<'a>),
+    /// Any other type, decode it with `Message::from_json`
+    Other(&'a str),
+}
+
+impl<'a> FastMessage<'a> {
+    pub fn product_id(&self) -> Option<&'a str> {
+        match self {
+            FastMessage::Heartbeat(x) => Some(x.product_id),
+            FastMessage::Ticker(x) => Some(x.product_id),
+            FastMessage::Snapshot(x) => Some(x.product_id),
+            FastMessage::L2update(x) => Some(x.product_id),
+            FastMessage::Received(x) => Some(x.product_id),
+            FastMessage::Open(x) => Some(x.product_id),
+            FastMessage::Done(x) => Some(x.product_id),
+            FastMessage::Match(x) => Some(x.product_id),
+            FastMessage::Change(x) => Some(x.product_id),
+            FastMessage::Other(_) => None,
+        }
+    }
+
+    pub fn sequence(&self) -> Option<usize> {
+        match self {
+            FastMessage::Heartbeat(x) => Some(x.sequence),
+            FastMessage::Ticker(x) => Some(x.sequence),
+            FastMessage::Received(x) => Some(x.sequence),
+            FastMessage::Open(x) => Some(x.sequence),
+            FastMessage::Done(x) => x.sequence,
+            FastMessage::Match(x) => Some(x.sequence),
+            FastMessage::Change(x) => Some(x.sequence),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Tag<'a> {
+    #[serde(rename = "type")]
+    _type: &'a str,
+}
+
+/// Coinbase sends `type` first, so it is found without parsing the whole frame
+fn sniff_type(text: &str) -> Option<&str> {
+    let rest = text.trim_start().strip_prefix('{')?.trim_start();
+    let rest = rest.strip_prefix(r#""type""#)?.trim_start();
+    let rest = rest.strip_prefix(':')?.trim_start().strip_prefix('"')?;
+    rest.find('"').map(|end| &rest[..end])
+}
+
+/// Decodes websocket text frame. Strings with escapes can't be borrowed and fail,
+/// Coinbase doesn't send them in fields of the decoded types
+pub fn decode(text: &str) -> Result<FastMessage<'_>, serde_json::Error> {
+    let tag = match sniff_type(text) {
+        Some(tag) => tag,
+        None => serde_json::from_str::<Tag>(text)?._type,
+    };
+    Ok(match tag {
+        "heartbeat" => FastMessage::Heartbeat(serde_json::from_str(text)?),
+        "ticker" => FastMessage::Ticker(serde_json::from_str(text)?),
+        "snapshot" => FastMessage::Snapshot(serde_json::from_str(text)?),
+        "l2update" => FastMessage::L2update(serde_json::from_str(text)?),
+        "received" => FastMessage::Received(serde_json::from_str(text)?),
+        "open" => FastMessage::Open(serde_json::from_str(text)?),
+        "done" => FastMessage::Done(serde_json::from_str(text)?),
+        "match" | "last_match" => FastMessage::Match(serde_json::from_str(text)?),
+        "change" => FastMessage::Change(serde_json::from_str(text)?),
+        other => FastMessage::Other(other),
+    })
+}
+
+/// Interner of product ids, every id is allocated once
+#[derive(Debug, Default, Clone)]
+pub struct ProductIds {
+    ids: HashSet<Arc<str>>,
+}
+
+impl ProductIds {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Shared copy of `product_id`
+    pub fn intern(&mut self, product_id: &str) -> Arc<str> {
+        if let Some(id) = self.ids.get(product_id) {
+            return id.clone();
+        }
+        let id: Arc<str> = Arc::from(product_id);
+        self.ids.insert(id.clone());
+        id
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay::RecordedFrame;
+    use crate::structs::wsfeed::{Full, Level2, Message};
+
+    #[test]
+    fn test_decode() {
+        let msg = decode(
+            r#"{"type":"match","trade_id":10,"sequence":50,"maker_order_id":"ac928c66-ca53-498f-9c13-a110027a60e8",
+                "taker_order_id":"132fb6ae-456b-4654-b4e0-d681ac05cea1","time":"2014-11-07T08:19:27.028459Z",
+                "product_id":"BTC-USD","size":"5.23512","price":"400.23","side":"sell"}"#,
+        )
+        .unwrap();
+        assert_eq!(Some("BTC-USD"), msg.product_id());
+        assert_eq!(Some(50), msg.sequence());
+        match msg {
+            FastMessage::Match(x) => {
+                assert_eq!(400.23, x.price.to_f64());
+                assert_eq!("5.23512", x.size.as_str());
+            }
+            other => panic!("{:?}", other),
+        }
+
+        let msg =
+            decode(r#"{ "product_id":"BTC-USD", "type" : "ticker", "sequence":1, "price":"" }"#);
+        assert!(msg.as_ref().unwrap().product_id() == Some("BTC-USD"));
+        assert_eq!(
+            FastMessage::Other("subscriptions"),
+            decode(r#"{"type":"subscriptions","channels":[]}"#).unwrap()
+        );
+        assert!(decode(r#"{"type":"open"}"#).is_err());
+    }
+
+    #[test]
+    fn test_intern() {
+        let mut ids = ProductIds::new();
+        let a = ids.intern("BTC-USD");
+        let b = ids.intern("BTC-USD");
+        ids.intern("ETH-USD");
+        assert!(Arc::ptr_eq(&a, &b));
+        assert_eq!(2, ids.len());
+    }
+
+    #[test]
+    fn test_same_as_message() {
+        let data = include_str!("../benches/data/full.jsonl");
+        for line in data.lines() {
+            let frame: RecordedFrame = serde_json::from_str(line).unwrap();
+            let fast = decode(&frame.frame).unwrap();
+            let msg = Message::from_json(&frame.frame).unwrap();
+            let (product_id, sequence) = match &msg {
+                Message::Heartbeat {
+                    product_id,
+                    sequence,
+                    ..
+                } => (Some(product_id.as_str()), Some(*sequence)),
+                Message::Ticker(x) => (Some(x.product_id()), Some(*x.sequence())),
+                Message::Level2(x) => (Some(x.product_id()), None),
+                Message::Match(x) => (Some(x.product_id.as_str()), Some(x.sequence)),
+                Message::Full(x) => (Some(x.product_id()), x.sequence().cloned()),
+                _ => (None, None),
+            };
+            assert_eq!((product_id, sequence), (fast.product_id(), fast.sequence()));
+            if let (Message::Full(Full::Match(x)), FastMessage::Match(y)) = (&msg, &fast) {
+                assert_eq!(x.price, y.price.to_f64());
+            }
+            if let (Message::Level2(Level2::L2update { changes, .. }), FastMessage::L2update(y)) =
+                (&msg, &fast)
+            {
+                assert_eq!(changes.len(), y.changes.len());
+            }
+        }
+    }
+}
@@ -0,0 +1,421 @@
+//! Contains hub sharing one websocket connection between many consumers
+//!
+//! [`FeedHub`] owns a single `WSFeed` connection. Every [`subscribe`](FeedHub::subscribe) adds
+//! references to its product/channel pairs: the first reference subscribes upstream, dropping
+//! the last [`Subscription`] unsubscribes. Subscriptions of the same product/channel pairs share
+//! a bounded broadcast channel, which gets only messages of these pairs, so a consumer falling
+//! behind gets `RecvError::Lagged` with the number of its own skipped messages. Connection errors
+//! are delivered to every subscription as `Message::InternalError` and the hub reconnects with
+//! the current subscriptions.
+//!
+//! ```no_run
+//! use coinbase_pro_rs::{hub::FeedHub, structs::wsfeed::ChannelType, WS_URL};
+//!
+//! # async fn run() {
+//! let hub = FeedHub::new(WS_URL);
+//! let mut tickers = hub.subscribe(&["BTC-USD"], &[ChannelType::Ticker]);
+//! let mut matches = hub.subscribe(&["BTC-USD"], &[ChannelType::Matches]);
+//!
+//! while let Ok(msg) = tickers.recv().await {
+//!     println!("{:?}", msg);
+//! }
+//! # }
+//! ```
+
+use futures::{future, SinkExt, StreamExt};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc};
+use tokio::time::Instant;
+use tokio_tungstenite::tungstenite::Message as TMessage;
+
+use crate::structs::wsfeed::{Channel, ChannelType, Message, Subscribe, SubscribeCmd};
+use crate::wsfeed::CBStream;
+use crate::WSFeed;
+
+pub use tokio::sync::broadcast::error::RecvError;
+
+const CAPACITY: usize = 1024;
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Channel with product, `None` for channels without products like `status`
+type Key = (ChannelType, Option<String>);
+
+enum Command {
+    Subscribe(Vec<Key>),
+    Unsubscribe(Vec<Key>),
+}
+
+/// Broadcast channel of every distinct set of keys
+type Groups = Arc<Mutex<HashMap<Vec<Key>, broadcast::Sender<Arc<Message>>>>>;
+
+/// Handle of the shared connection, clones share the same connection
+#[derive(Clone)]
+pub struct FeedHub {
+    commands: mpsc::UnboundedSender<Command>,
+    groups: Groups,
+    capacity: usize,
+}
+
+impl FeedHub {
+    /// Spawns the task owning the connection, it connects on the first subscription
+    pub fn new(uri: &str) -> Self {
+        Self::with_capacity(uri, CAPACITY)
+    }
+
+    /// Hub buffering up to `capacity` messages for every subscription
+    pub fn with_capacity(uri: &str, capacity: usize) -> Self {
+        let (commands, rx) = mpsc::unbounded_channel();
+        let groups = Groups::default();
+        tokio::spawn(run(uri.to_string(), rx, groups.clone()));
+        FeedHub {
+            commands,
+            groups,
+            capacity,
+        }
+    }
+
+    /// Subscribes to every channel for every product, `product_ids` may be empty for
+    /// channels like `status`
+    pub fn subscribe(
+        &self,
+        product_ids: &[impl AsRef<str>],
+        channels: &[ChannelType],
+    ) -> Subscription {
+        let mut keys: Vec<Key> = channels
+            .iter()
+            .flat_map(|channel| {
+                let products: Vec<_> = if product_ids.is_empty() {
+                    vec![None]
+                } else {
+                    product_ids
+                        .iter()
+                        .map(|x| Some(x.as_ref().to_string()))
+                        .collect()
+                };
+                products.into_iter().map(move |x| (channel.clone(), x))
+            })
+            .collect();
+        keys.sort_by(|(a, x), (b, y)| (a.as_str(), x).cmp(&(b.as_str(), y)));
+        keys.dedup();
+        // receiver is created first, so no message after the subscription is missed
+        let rx = self
+            .groups
+            .lock()
+            .unwrap()
+            .entry(keys.clone())
+            .or_insert_with(|| broadcast::channel(self.capacity).0)
+            .subscribe();
+        let _ = self.commands.send(Command::Subscribe(keys.clone()));
+        Subscription {
+            keys,
+            rx,
+            commands: self.commands.clone(),
+        }
+    }
+
+    /// Number of live subscriptions
+    pub fn subscriptions(&self) -> usize {
+        let groups = self.groups.lock().unwrap();
+        groups.values().map(|x| x.receiver_count()).sum()
+    }
+}
+
+/// Messages of the subscribed products and channels, unsubscribes when dropped
+pub struct Subscription {
+    keys: Vec<Key>,
+    rx: broadcast::Receiver<Arc<Message>>,
+    commands: mpsc::UnboundedSender<Command>,
+}
+
+impl Subscription {
+    /// Next message of this subscription. Messages not bound to a channel (subscriptions,
+    /// errors) are received by every subscription
+    pub async fn recv(&mut self) -> Result<Arc<Message>, RecvError> {
+        self.rx.recv().await
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        let keys = std::mem::take(&mut self.keys);
+        let _ = self.commands.send(Command::Unsubscribe(keys));
+    }
+}
+
+/// Message belongs to one of `keys`
+fn wants(keys: &[Key], msg: &Message) -> bool {
+    let (channels, product_id) = match route(msg) {
+        Some(route) => route,
+        None => return true,
+    };
+    keys.iter().any(|(channel, id)| {
+        channels.contains(channel) && (id.is_none() || id.as_deref() == product_id)
+    })
+}
+
+/// Sends `msg` to the groups which want it, drops groups without subscriptions
+fn publish(groups: &Groups, msg: Message) {
+    let msg = Arc::new(msg);
+    let mut groups = groups.lock().unwrap();
+    groups.retain(|keys, tx| {
+        if wants(keys, &msg) {
+            // no subscribers is not an error
+            let _ = tx.send(msg.clone());
+        }
+        tx.receiver_count() > 0
+    });
+}
+
+/// Channels which can produce the message and its product
+fn route(msg: &Message) -> Option<(&'static [ChannelType], Option<&str>)> {
+    use ChannelType::*;
+    let route: (&'static [ChannelType], _) = match msg {
+        Message::Heartbeat { product_id, .. } => (&[Heartbeat], Some(product_id.as_str())),
+        Message::Status { .. } => (&[Status], None),
+        Message::Ticker(x) => (&[Ticker, TickerBatch], Some(x.product_id())),
+        Message::Level2(x) => (&[Level2, Level2Batch, Level2Fifty], Some(x.product_id())),
+        Message::Match(x) => (&[Matches], Some(x.product_id.as_str())),
+        Message::Full(x) => (&[Full, User], Some(x.product_id())),
+        Message::Auction(x) => (&[Auctionfeed], Some(x.product_id.as_str())),
+        Message::RfqMatch(x) => (&[RfqMatches], Some(x.product_id.as_str())),
+        _ => return None,
+    };
+    Some(route)
+}
+
+fn command<'a>(cmd: SubscribeCmd, keys: impl Iterator<Item = &'a Key>) -> Subscribe {
+    let channels = keys
+        .map(|(name, product_id)| match product_id {
+            Some(id) => Channel::WithProduct {
+                name: name.clone(),
+                product_ids: vec![id.clone()],
+            },
+            None => Channel::Name(name.clone()),
+        })
+        .collect();
+    Subscribe {
+        _type: cmd,
+        product_ids: vec![],
+        channels,
+        auth: None,
+    }
+}
+
+async fn next<S: CBStream>(conn: &mut Option<S>) -> Option<S::Item> {
+    match conn {
+        Some(conn) => conn.next().await,
+        None => future::pending().await,
+    }
+}
+
+/// Waits until `at`, forever if it is not set
+async fn sleep_until(at: Option<Instant>) {
+    match at {
+        Some(at) => tokio::time::sleep_until(at).await,
+        None => future::pending().await,
+    }
+}
+
+async fn run(uri: String, mut commands: mpsc::UnboundedReceiver<Command>, groups: Groups) {
+    let mut counts: HashMap<Key, usize> = HashMap::new();
+    let mut conn = None;
+    // reconnect is delayed after a failure, commands are handled meanwhile
+    let mut retry_at = None;
+
+    loop {
+        let ready = retry_at.map(|x| x <= Instant::now()).unwrap_or(true);
+        if conn.is_none() && !counts.is_empty() && ready {
+            let subscribe = command(SubscribeCmd::Subscribe, counts.keys());
+            match WSFeed::connect_with_sub(&uri, subscribe).await {
+                Ok(stream) => conn = Some(stream),
+                Err(e) => {
+                    publish(&groups, Message::InternalError(e));
+                    retry_at = Some(Instant::now() + RECONNECT_DELAY);
+                    continue;
+                }
+            }
+        }
+
+        let mut update = None;
+        tokio::select! {
+            cmd = commands.recv() => match cmd {
+                Some(Command::Subscribe(keys)) => {
+                    let mut added = vec![];
+                    for key in keys {
+                        let count = counts.entry(key.clone()).or_insert(0);
+                        *count += 1;
+                        if *count == 1 {
+                            added.push(key);
+                        }
+                    }
+                    if !added.is_empty() {
+                        update = Some(command(SubscribeCmd::Subscribe, added.iter()));
+                    }
+                }
+                Some(Command::Unsubscribe(keys)) => {
+                    let mut removed = vec![];
+                    for key in keys {
+                        if let Some(count) = counts.get_mut(&key) {
+                            *count -= 1;
+                            if *count == 0 {
+                                counts.remove(&key);
+                                removed.push(key);
+                            }
+                        }
+                    }
+                    if counts.is_empty() {
+                        log::debug!("no subscriptions left, closing connection");
+                        conn = None;
+                    } else if !removed.is_empty() {
+                        update = Some(command(SubscribeCmd::Unsubscribe, removed.iter()));
+                    }
+                }
+                None => return,
+            },
+            _ = sleep_until(retry_at), if conn.is_none() && !counts.is_empty() => {}
+            msg = next(&mut conn) => match msg {
+                Some(Ok(msg)) => publish(&groups, msg),
+                Some(Err(e)) => {
+                    log::warn!("feed hub reconnecting: {}", e);
+                    publish(&groups, Message::InternalError(e));
+                    conn = None;
+                    retry_at = Some(Instant::now() + RECONNECT_DELAY);
+                }
+                None => {
+                    conn = None;
+                    retry_at = Some(Instant::now() + RECONNECT_DELAY);
+                }
+            },
+        }
+
+        if let (Some(stream), Some(update)) = (conn.as_mut(), update) {
+            let update = TMessage::Text(serde_json::to_string(&update).unwrap());
+            if let Err(e) = stream.send(update).await {
+                publish(&groups, Message::InternalError(e));
+                conn = None;
+                retry_at = Some(Instant::now() + RECONNECT_DELAY);
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock::MockServer;
+
+    fn heartbeat(product_id: &str) -> String {
+        format!(
+            r#"{{"type":"heartbeat","last_trade_id":20,"product_id":"{}","sequence":10,
+                "time":"2019-08-21T22:10:15.190000Z"}}"#,
+            product_id
+        )
+    }
+
+    fn subscribed(msg: &Message) -> Vec<String> {
+        match msg {
+            Message::Subscriptions { channels } => match channels.as_slice() {
+                [Channel::WithProduct { product_ids, .. }] => product_ids.clone(),
+                other => panic!("{:?}", other),
+            },
+            other => panic!("{:?}", other),
+        }
+    }
+
+    fn product_id(msg: &Message) -> &str {
+        match msg {
+            Message::Heartbeat { product_id, .. } => product_id,
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hub() {
+        let server = MockServer::start();
+        let hub = FeedHub::new(&server.ws_uri());
+
+        let mut btc = hub.subscribe(&["BTC-USD"], &[ChannelType::Heartbeat]);
+        assert_eq!(vec!["BTC-USD"], subscribed(&btc.recv().await.unwrap()));
+
+        let mut all = hub.subscribe(&["BTC-USD", "ETH-USD"], &[ChannelType::Heartbeat]);
+        assert_eq!(
+            vec!["BTC-USD", "ETH-USD"],
+            subscribed(&all.recv().await.unwrap())
+        );
+        assert_eq!(
+            vec!["BTC-USD", "ETH-USD"],
+            subscribed(&btc.recv().await.unwrap())
+        );
+        assert_eq!(2, hub.subscriptions());
+
+        server.ws_send(&heartbeat("ETH-USD"));
+        server.ws_send(&heartbeat("BTC-USD"));
+        assert_eq!("ETH-USD", product_id(&all.recv().await.unwrap()));
+        assert_eq!("BTC-USD", product_id(&all.recv().await.unwrap()));
+        assert_eq!("BTC-USD", product_id(&btc.recv().await.unwrap()));
+
+        drop(all);
+        assert_eq!(vec!["BTC-USD"], subscribed(&btc.recv().await.unwrap()));
+    }
+
+    #[tokio::test]
+    async fn test_lagged() {
+        let server = MockServer::start();
+        let hub = FeedHub::with_capacity(&server.ws_uri(), 2);
+        let mut sub = hub.subscribe(&["BTC-USD"], &[ChannelType::Heartbeat]);
+        sub.recv().await.unwrap();
+        let mut eth = hub.subscribe(&["ETH-USD"], &[ChannelType::Heartbeat]);
+        eth.recv().await.unwrap();
+        sub.recv().await.unwrap();
+
+        for _ in 0..4 {
+            server.ws_send(&heartbeat("BTC-USD"));
+        }
+        // frames are published in order, so all BTC-USD ones are in once ETH-USD arrives
+        server.ws_send(&heartbeat("ETH-USD"));
+        assert_eq!("ETH-USD", product_id(&eth.recv().await.unwrap()));
+        assert_eq!(Err(RecvError::Lagged(2)), sub.recv().await);
+        assert_eq!("BTC-USD", product_id(&sub.recv().await.unwrap()));
+    }
+
+    #[tokio::test]
+    async fn test_lagged_by_own_messages() {
+        let server = MockServer::start();
+        let hub = FeedHub::with_capacity(&server.ws_uri(), 2);
+        let mut btc = hub.subscribe(&["BTC-USD"], &[ChannelType::Heartbeat]);
+        btc.recv().await.unwrap();
+        let mut eth = hub.subscribe(&["ETH-USD"], &[ChannelType::Heartbeat]);
+        eth.recv().await.unwrap();
+        btc.recv().await.unwrap();
+
+        for _ in 0..4 {
+            server.ws_send(&heartbeat("ETH-USD"));
+        }
+        server.ws_send(&heartbeat("BTC-USD"));
+        assert_eq!("BTC-USD", product_id(&btc.recv().await.unwrap()));
+        assert_eq!(Err(RecvError::Lagged(2)), eth.recv().await);
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_while_reconnecting() {
+        let server = MockServer::start();
+        let hub = FeedHub::new(&server.ws_uri());
+        let mut btc = hub.subscribe(&["BTC-USD"], &[ChannelType::Heartbeat]);
+        assert_eq!(vec!["BTC-USD"], subscribed(&btc.recv().await.unwrap()));
+
+        server.ws_close(1000, "bye");
+        assert!(matches!(
+            *btc.recv().await.unwrap(),
+            Message::InternalError(_)
+        ));
+        let mut eth = hub.subscribe(&["ETH-USD"], &[ChannelType::Heartbeat]);
+        // reconnect subscribes to all keys at once, in no particular order
+        for sub in [&mut eth, &mut btc].iter_mut() {
+            let mut products = subscribed(&sub.recv().await.unwrap());
+            products.sort();
+            assert_eq!(vec!["BTC-USD", "ETH-USD"], products);
+        }
+    }
+}
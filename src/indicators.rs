@@ -0,0 +1,460 @@
+//! Contains technical indicators computed over candles and ticker streams
+//!
+//! Every indicator is incremental: values are pushed one at a time and the current value is
+//! returned once the indicator has enough data. The same instance works for backtests over
+//! `Vec<Candle>` with [`batch`] and for live data from `WSFeed` with `push_ticker`.
+//!
+//! ```
+//! use coinbase_pro_rs::indicators::{batch, Sma};
+//! # let candles: Vec<coinbase_pro_rs::structs::public::Candle> = vec![];
+//! let sma: Vec<Option<f64>> = batch(Sma::new(20), &candles);
+//! ```
+
+use std::collections::VecDeque;
+
+use crate::structs::public::Candle;
+use crate::structs::wsfeed::{Full, Match, Message, Ticker};
+
+/// Indicator fed by candles
+pub trait Indicator {
+    type Output;
+
+    /// Adds candle, returns new value when enough data collected
+    fn push(&mut self, candle: &Candle) -> Option<Self::Output>;
+
+    /// Value after the last push
+    fn value(&self) -> Option<Self::Output>;
+}
+
+/// Feeds all candles in order, result has value per candle
+pub fn batch<I: Indicator>(mut indicator: I, candles: &[Candle]) -> Vec<Option<I::Output>> {
+    candles.iter().map(|x| indicator.push(x)).collect()
+}
+
+fn ticker_price(ticker: &Ticker) -> Option<f64> {
+    Some(*ticker.price()).filter(|x| !x.is_nan())
+}
+
+/// Simple moving average of close prices
+#[derive(Debug, Clone)]
+pub struct Sma {
+    period: usize,
+    window: VecDeque<f64>,
+    sum: f64,
+}
+
+impl Sma {
+    pub fn new(period: usize) -> Self {
+        assert!(period > 0, "period should be positive");
+        Sma {
+            period,
+            window: VecDeque::with_capacity(period + 1),
+            sum: 0.0,
+        }
+    }
+
+    pub fn push_price(&mut self, price: f64) -> Option<f64> {
+        self.window.push_back(price);
+        self.sum += price;
+        if self.window.len() > self.period {
+            self.sum -= self.window.pop_front().unwrap();
+        }
+        self.value()
+    }
+
+    /// Uses last price, `Ticker::Empty` without price is skipped
+    pub fn push_ticker(&mut self, ticker: &Ticker) -> Option<f64> {
+        match ticker_price(ticker) {
+            Some(price) => self.push_price(price),
+            None => self.value(),
+        }
+    }
+}
+
+impl Indicator for Sma {
+    type Output = f64;
+
+    fn push(&mut self, candle: &Candle) -> Option<f64> {
+        self.push_price(candle.close)
+    }
+
+    fn value(&self) -> Option<f64> {
+        if self.window.len() == self.period {
+            Some(self.sum / self.period as f64)
+        } else {
+            None
+        }
+    }
+}
+
+/// Exponential moving average of close prices, seeded with SMA of the first `period` values
+#[derive(Debug, Clone)]
+pub struct Ema {
+    alpha: f64,
+    seed: Sma,
+    value: Option<f64>,
+}
+
+impl Ema {
+    pub fn new(period: usize) -> Self {
+        Ema {
+            alpha: 2.0 / (period as f64 + 1.0),
+            seed: Sma::new(period),
+            value: None,
+        }
+    }
+
+    pub fn push_price(&mut self, price: f64) -> Option<f64> {
+        self.value = match self.value {
+            Some(prev) => Some(prev + self.alpha * (price - prev)),
+            None => self.seed.push_price(price),
+        };
+        self.value
+    }
+
+    /// Uses last price, `Ticker::Empty` without price is skipped
+    pub fn push_ticker(&mut self, ticker: &Ticker) -> Option<f64> {
+        match ticker_price(ticker) {
+            Some(price) => self.push_price(price),
+            None => self.value,
+        }
+    }
+}
+
+impl Indicator for Ema {
+    type Output = f64;
+
+    fn push(&mut self, candle: &Candle) -> Option<f64> {
+        self.push_price(candle.close)
+    }
+
+    fn value(&self) -> Option<f64> {
+        self.value
+    }
+}
+
+/// Wilder's smoothing: average of the first `period` values, then `(prev * (n - 1) + x) / n`
+#[derive(Debug, Clone)]
+struct Wilder {
+    period: usize,
+    count: usize,
+    value: f64,
+}
+
+impl Wilder {
+    fn new(period: usize) -> Self {
+        assert!(period > 0, "period should be positive");
+        Wilder {
+            period,
+            count: 0,
+            value: 0.0,
+        }
+    }
+
+    fn push(&mut self, x: f64) -> Option<f64> {
+        let n = self.period as f64;
+        if self.count < self.period {
+            self.count += 1;
+            self.value += x / n;
+        } else {
+            self.value = (self.value * (n - 1.0) + x) / n;
+        }
+        self.get()
+    }
+
+    fn get(&self) -> Option<f64> {
+        if self.count == self.period {
+            Some(self.value)
+        } else {
+            None
+        }
+    }
+}
+
+/// Relative strength index (0..100) of close prices with Wilder's smoothing
+#[derive(Debug, Clone)]
+pub struct Rsi {
+    prev: Option<f64>,
+    gain: Wilder,
+    loss: Wilder,
+}
+
+impl Rsi {
+    pub fn new(period: usize) -> Self {
+        Rsi {
+            prev: None,
+            gain: Wilder::new(period),
+            loss: Wilder::new(period),
+        }
+    }
+
+    pub fn push_price(&mut self, price: f64) -> Option<f64> {
+        if let Some(prev) = self.prev.replace(price) {
+            let change = price - prev;
+            self.gain.push(change.max(0.0));
+            self.loss.push((-change).max(0.0));
+        }
+        self.value()
+    }
+
+    /// Uses last price, `Ticker::Empty` without price is skipped
+    pub fn push_ticker(&mut self, ticker: &Ticker) -> Option<f64> {
+        match ticker_price(ticker) {
+            Some(price) => self.push_price(price),
+            None => self.value(),
+        }
+    }
+}
+
+impl Indicator for Rsi {
+    type Output = f64;
+
+    fn push(&mut self, candle: &Candle) -> Option<f64> {
+        self.push_price(candle.close)
+    }
+
+    fn value(&self) -> Option<f64> {
+        let (gain, loss) = (self.gain.get()?, self.loss.get()?);
+        if loss == 0.0 {
+            Some(if gain == 0.0 { 50.0 } else { 100.0 })
+        } else {
+            Some(100.0 - 100.0 / (1.0 + gain / loss))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bands {
+    pub lower: f64,
+    pub middle: f64,
+    pub upper: f64,
+}
+
+/// Bollinger bands: SMA of close prices -/+ `k` population standard deviations
+#[derive(Debug, Clone)]
+pub struct Bollinger {
+    sma: Sma,
+    k: f64,
+}
+
+impl Bollinger {
+    pub fn new(period: usize, k: f64) -> Self {
+        Bollinger {
+            sma: Sma::new(period),
+            k,
+        }
+    }
+
+    pub fn push_price(&mut self, price: f64) -> Option<Bands> {
+        self.sma.push_price(price);
+        self.value()
+    }
+
+    /// Uses last price, `Ticker::Empty` without price is skipped
+    pub fn push_ticker(&mut self, ticker: &Ticker) -> Option<Bands> {
+        match ticker_price(ticker) {
+            Some(price) => self.push_price(price),
+            None => self.value(),
+        }
+    }
+}
+
+impl Indicator for Bollinger {
+    type Output = Bands;
+
+    fn push(&mut self, candle: &Candle) -> Option<Bands> {
+        self.push_price(candle.close)
+    }
+
+    fn value(&self) -> Option<Bands> {
+        let middle = self.sma.value()?;
+        let window = &self.sma.window;
+        let var = window.iter().map(|x| (x - middle).powi(2)).sum::<f64>() / window.len() as f64;
+        let width = self.k * var.sqrt();
+        Some(Bands {
+            lower: middle - width,
+            middle,
+            upper: middle + width,
+        })
+    }
+}
+
+/// Average true range with Wilder's smoothing
+#[derive(Debug, Clone)]
+pub struct Atr {
+    prev_close: Option<f64>,
+    tr: Wilder,
+}
+
+impl Atr {
+    pub fn new(period: usize) -> Self {
+        Atr {
+            prev_close: None,
+            tr: Wilder::new(period),
+        }
+    }
+}
+
+impl Indicator for Atr {
+    type Output = f64;
+
+    fn push(&mut self, candle: &Candle) -> Option<f64> {
+        let tr = match self.prev_close.replace(candle.close) {
+            Some(close) => candle.high.max(close) - candle.low.min(close),
+            None => candle.range(),
+        };
+        self.tr.push(tr)
+    }
+
+    fn value(&self) -> Option<f64> {
+        self.tr.get()
+    }
+}
+
+/// Cumulative volume weighted average price since creation or [`reset`](Vwap::reset),
+/// candles contribute with their typical price
+#[derive(Debug, Clone, Default)]
+pub struct Vwap {
+    pv: f64,
+    volume: f64,
+}
+
+impl Vwap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts new session, eg: at the beginning of a day
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn push_trade(&mut self, price: f64, size: f64) -> Option<f64> {
+        self.pv += price * size;
+        self.volume += size;
+        self.value()
+    }
+
+    pub fn push_match(&mut self, m: &Match) -> Option<f64> {
+        self.push_trade(m.price, m.size)
+    }
+
+    /// Uses last trade of `Ticker::Full`, others are skipped
+    pub fn push_ticker(&mut self, ticker: &Ticker) -> Option<f64> {
+        match ticker {
+            Ticker::Full {
+                price, last_size, ..
+            } => self.push_trade(*price, *last_size),
+            Ticker::Empty { .. } => self.value(),
+        }
+    }
+
+    /// Adds `Message::Match`, `Full::Match` or `Message::Ticker`, skips others
+    pub fn push_message(&mut self, msg: &Message) -> Option<f64> {
+        match msg {
+            Message::Match(m) | Message::Full(Full::Match(m)) => self.push_match(m),
+            Message::Ticker(ticker) => self.push_ticker(ticker),
+            _ => self.value(),
+        }
+    }
+}
+
+impl Indicator for Vwap {
+    type Output = f64;
+
+    fn push(&mut self, candle: &Candle) -> Option<f64> {
+        self.push_trade(candle.typical_price(), candle.volume)
+    }
+
+    fn value(&self) -> Option<f64> {
+        if self.volume > 0.0 {
+            Some(self.pv / self.volume)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    fn candles(closes: &[f64]) -> Vec<Candle> {
+        closes
+            .iter()
+            .enumerate()
+            .map(|(i, x)| {
+                let time = Utc.timestamp_opt(i as i64 * 60, 0).unwrap();
+                Candle::new(time, *x, x + 1.0, x - 1.0, *x, 1.0)
+            })
+            .collect()
+    }
+
+    fn assert_close(expected: f64, value: Option<f64>) {
+        let value = value.unwrap();
+        assert!((expected - value).abs() < 1e-9, "{} != {}", expected, value);
+    }
+
+    #[test]
+    fn test_sma_ema() {
+        let data = candles(&[1.0, 2.0, 3.0, 4.0, 5.0]);
+        let sma = batch(Sma::new(3), &data);
+        assert_eq!(vec![None, None, Some(2.0), Some(3.0), Some(4.0)], sma);
+
+        let ema = batch(Ema::new(3), &data);
+        assert_eq!(None, ema[1]);
+        assert_close(2.0, ema[2]);
+        assert_close(3.0, ema[3]);
+        assert_close(4.0, ema[4]);
+
+        let mut ema = Ema::new(3);
+        for x in &[1.0, 2.0, 3.0] {
+            ema.push_price(*x);
+        }
+        assert_close(6.0, ema.push_price(10.0));
+    }
+
+    #[test]
+    fn test_rsi() {
+        let mut rsi = Rsi::new(2);
+        assert_eq!(None, rsi.push_price(1.0));
+        assert_eq!(None, rsi.push_price(2.0));
+        assert_close(50.0, rsi.push_price(1.0));
+        // gain (0.5 + 1) / 2, loss (0.5 + 0) / 2
+        assert_close(75.0, rsi.push_price(2.0));
+        let up = batch(Rsi::new(3), &candles(&[1.0, 2.0, 3.0, 4.0]));
+        assert_eq!(Some(100.0), up[3]);
+    }
+
+    #[test]
+    fn test_bollinger() {
+        let bands = batch(Bollinger::new(4, 2.0), &candles(&[2.0, 4.0, 4.0, 6.0]));
+        assert_eq!(
+            Some(Bands {
+                lower: 4.0 - 2.0 * 2f64.sqrt(),
+                middle: 4.0,
+                upper: 4.0 + 2.0 * 2f64.sqrt(),
+            }),
+            bands[3]
+        );
+    }
+
+    #[test]
+    fn test_atr() {
+        // true ranges: 2, 2, 3 (high 5 - prev close 2)
+        let data = candles(&[1.0, 2.0, 4.0]);
+        let atr = batch(Atr::new(2), &data);
+        assert_eq!(vec![None, Some(2.0), Some(2.5)], atr);
+    }
+
+    #[test]
+    fn test_vwap() {
+        let mut vwap = Vwap::new();
+        assert_eq!(None, vwap.value());
+        vwap.push_trade(10.0, 1.0);
+        assert_eq!(Some(12.25), vwap.push_trade(13.0, 3.0));
+        vwap.reset();
+        assert_eq!(Some(2.0), batch(vwap, &candles(&[1.0, 3.0]))[1]);
+    }
+}
@@ -0,0 +1,66 @@
+//! Contains JSON parsing of websocket frames and REST bodies
+//!
+//! `serde_json` by default, `simd-json` with the `simd-json` feature. Errors of both backends
+//! are `serde_json::Error`, so `Message::from_json` and `CBError::Serde` don't depend on it.
+//! `simd-json` parses in place: [`from_slice_mut`] takes the owned buffer of the caller, the
+//! borrowing functions copy the input first.
+
+use serde::de::DeserializeOwned;
+
+#[cfg(not(feature = "simd-json"))]
+pub(crate) fn from_str<T: DeserializeOwned>(text: &str) -> Result<T, serde_json::Error> {
+    serde_json::from_str(text)
+}
+
+#[cfg(not(feature = "simd-json"))]
+pub(crate) fn from_slice<T: DeserializeOwned>(data: &[u8]) -> Result<T, serde_json::Error> {
+    serde_json::from_slice(data)
+}
+
+#[cfg(not(feature = "simd-json"))]
+pub(crate) fn from_slice_mut<T: DeserializeOwned>(data: &mut [u8]) -> Result<T, serde_json::Error> {
+    serde_json::from_slice(data)
+}
+
+#[cfg(feature = "simd-json")]
+pub(crate) fn from_str<T: DeserializeOwned>(text: &str) -> Result<T, serde_json::Error> {
+    from_slice(text.as_bytes())
+}
+
+/// simd-json parses in place, so `data` is copied first
+#[cfg(feature = "simd-json")]
+pub(crate) fn from_slice<T: DeserializeOwned>(data: &[u8]) -> Result<T, serde_json::Error> {
+    from_slice_mut(&mut data.to_vec())
+}
+
+/// Parses without copying, escaped strings of `data` are decoded in place even on error
+#[cfg(feature = "simd-json")]
+pub(crate) fn from_slice_mut<T: DeserializeOwned>(data: &mut [u8]) -> Result<T, serde_json::Error> {
+    simd_json::serde::from_slice(data).map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::wsfeed::Message;
+
+    #[test]
+    fn test_from_str() {
+        let xs: Vec<f64> = from_str("[1.5, 2]").unwrap();
+        assert_eq!(vec![1.5, 2.0], xs);
+        assert!(from_str::<Vec<f64>>("[1.5,").is_err());
+        assert!(from_slice::<Message>(br#"{"type":"heartbeat"}"#).is_err());
+    }
+
+    #[test]
+    fn test_from_slice_mut() {
+        let mut data = br#"{"type":"error","message":"a \"b\""}"#.to_vec();
+        let msg: Message = from_slice_mut(&mut data).unwrap();
+        assert_eq!(
+            Message::Error {
+                message: r#"a "b""#.to_string()
+            },
+            msg
+        );
+    }
+}
@@ -0,0 +1,200 @@
+//! Contains exchange-to-local latency statistics of the websocket feed
+//!
+//! [`LatencyStats`] collects [`Envelope::latency`] of messages received from
+//! `WSFeed::connect_with_envelope` per channel. The latency includes the local clock offset,
+//! so it is negative if the local clock is behind the exchange one.
+//!
+//! ```no_run
+//! use coinbase_pro_rs::latency::LatencyStats;
+//! use coinbase_pro_rs::structs::wsfeed::{Channel, ChannelType, Subscribe, SubscribeCmd};
+//! use coinbase_pro_rs::{WSFeed, WS_URL};
+//! use futures::StreamExt;
+//!
+//! # async fn run() {
+//! let subscribe = Subscribe {
+//!     _type: SubscribeCmd::Subscribe,
+//!     product_ids: vec!["BTC-USD".to_string()],
+//!     channels: vec![Channel::Name(ChannelType::Matches)],
+//!     auth: None,
+//! };
+//! let mut stream = WSFeed::connect_with_envelope(WS_URL, subscribe)
+//!     .await
+//!     .unwrap();
+//! let mut stats = LatencyStats::new();
+//! while let Some(Ok(envelope)) = stream.next().await {
+//!     stats.push(&envelope);
+//!     println!("{:?}", stats.get(&ChannelType::Matches));
+//! }
+//! # }
+//! ```
+
+use chrono::Duration;
+use std::collections::HashMap;
+
+use crate::structs::wsfeed::{ChannelType, Envelope};
+
+/// Latency of one channel
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChannelLatency {
+    pub count: u64,
+    pub min: Duration,
+    pub max: Duration,
+    pub last: Duration,
+    /// Sum of latencies in nanoseconds, wide enough to never overflow
+    total: i128,
+}
+
+impl ChannelLatency {
+    fn new(latency: Duration) -> Self {
+        ChannelLatency {
+            count: 1,
+            min: latency,
+            max: latency,
+            last: latency,
+            total: nanoseconds(latency),
+        }
+    }
+
+    fn push(&mut self, latency: Duration) {
+        self.count += 1;
+        self.min = self.min.min(latency);
+        self.max = self.max.max(latency);
+        self.last = latency;
+        self.total += nanoseconds(latency);
+    }
+
+    pub fn mean(&self) -> Duration {
+        Duration::nanoseconds((self.total / self.count as i128) as i64)
+    }
+}
+
+fn nanoseconds(latency: Duration) -> i128 {
+    let millis = i128::from(latency.num_milliseconds()) * 1_000_000;
+    latency.num_nanoseconds().map_or(millis, i128::from)
+}
+
+/// Latency per channel
+#[derive(Debug, Clone, Default)]
+pub struct LatencyStats {
+    channels: HashMap<ChannelType, ChannelLatency>,
+}
+
+impl LatencyStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds latency of the message, messages without channel or exchange time are skipped
+    pub fn push(&mut self, envelope: &Envelope) -> Option<Duration> {
+        let channel = envelope.message.channel()?;
+        let latency = envelope.latency()?;
+        self.channels
+            .entry(channel)
+            .and_modify(|x| x.push(latency))
+            .or_insert_with(|| ChannelLatency::new(latency));
+        Some(latency)
+    }
+
+    pub fn get(&self, channel: &ChannelType) -> Option<&ChannelLatency> {
+        self.channels.get(channel)
+    }
+
+    pub fn channels(&self) -> impl Iterator<Item = (&ChannelType, &ChannelLatency)> {
+        self.channels.iter()
+    }
+
+    pub fn reset(&mut self) {
+        self.channels.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::wsfeed::Message;
+    use crate::utils::delay;
+    use crate::{WSFeed, WS_SANDBOX_URL};
+    use tokio::time::Instant;
+
+    fn envelope(message: Message, received: &str) -> Envelope {
+        Envelope {
+            message,
+            received: received.parse().unwrap(),
+            instant: Instant::now(),
+            connection_id: 0,
+            frame_size: 0,
+        }
+    }
+
+    fn heartbeat(time: &str) -> Message {
+        Message::Heartbeat {
+            sequence: 1,
+            last_trade_id: 1,
+            product_id: "BTC-USD".to_string(),
+            time: time.parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_push() {
+        let mut stats = LatencyStats::new();
+        let latency = stats.push(&envelope(
+            heartbeat("2021-03-01T12:00:00.000000Z"),
+            "2021-03-01T12:00:00.010000Z",
+        ));
+        assert_eq!(Some(Duration::milliseconds(10)), latency);
+        stats.push(&envelope(
+            heartbeat("2021-03-01T12:00:01.000000Z"),
+            "2021-03-01T12:00:01.030000Z",
+        ));
+        let skipped = envelope(
+            Message::Error {
+                message: "test".to_string(),
+            },
+            "2021-03-01T12:00:01.030000Z",
+        );
+        assert_eq!(None, stats.push(&skipped));
+
+        let heartbeats = stats.get(&ChannelType::Heartbeat).unwrap();
+        assert_eq!(2, heartbeats.count);
+        assert_eq!(Duration::milliseconds(10), heartbeats.min);
+        assert_eq!(Duration::milliseconds(30), heartbeats.max);
+        assert_eq!(Duration::milliseconds(20), heartbeats.mean());
+        assert_eq!(1, stats.channels().count());
+    }
+
+    #[test]
+    fn test_mean_many() {
+        let mut latency = ChannelLatency::new(Duration::milliseconds(10));
+        latency.count = 1 << 32;
+        latency.total = nanoseconds(Duration::milliseconds(10)) << 32;
+        assert_eq!(Duration::milliseconds(10), latency.mean());
+        latency.push(Duration::milliseconds(10));
+        assert_eq!(Duration::milliseconds(10), latency.mean());
+    }
+
+    #[tokio::test]
+    #[cfg_attr(not(feature = "latency-tests"), ignore)] // region & clock dependent
+    #[serial]
+    async fn test_check_feed_latency() {
+        use crate::structs::wsfeed::{Channel, Subscribe, SubscribeCmd};
+        use futures::StreamExt;
+
+        delay();
+        let subscribe = Subscribe {
+            _type: SubscribeCmd::Subscribe,
+            product_ids: vec!["BTC-USD".to_string()],
+            channels: vec![Channel::Name(ChannelType::Heartbeat)],
+            auth: None,
+        };
+        let mut stream = WSFeed::connect_with_envelope(WS_SANDBOX_URL, subscribe)
+            .await
+            .unwrap();
+        let mut stats = LatencyStats::new();
+        while stats.get(&ChannelType::Heartbeat).map(|x| x.count) < Some(3) {
+            stats.push(&stream.next().await.unwrap().unwrap());
+        }
+        let latency = dbg!(stats.get(&ChannelType::Heartbeat).unwrap().mean());
+        assert!(latency <= Duration::milliseconds(500), "too slow")
+    }
+}
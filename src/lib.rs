@@ -67,15 +67,33 @@
 //! ```
 
 pub mod adapters;
+pub mod api;
+pub mod auction;
+pub mod buffer;
+pub mod candles;
+pub mod catalog;
+pub mod demux;
 mod error;
+pub mod fast;
+pub mod hub;
+pub mod indicators;
+mod json;
+pub mod latency;
+#[cfg(feature = "mock")]
+pub mod mock;
 pub mod private;
 pub mod public;
+pub mod replay;
+pub mod shards;
 pub mod structs;
+pub mod tickers;
 mod utils;
+pub mod watchdog;
 
 pub mod wsfeed;
 
 pub use crate::adapters::{ASync, Sync};
+pub use crate::api::{PrivateApi, PublicApi};
 pub use crate::error::{CBError, WSError};
 pub use crate::private::Private;
 pub use crate::public::Public;
@@ -0,0 +1,650 @@
+//! Contains local mock of Coinbase api for tests without network
+//!
+//! Available with `mock` feature. [`MockServer`] serves REST endpoints and websocket-feed on
+//! localhost with canned or scripted responses. `Public`, `Private` and `WSFeed` are pointed at
+//! it with [`MockServer::uri`] and [`MockServer::ws_uri`] instead of `SANDBOX_URL`.
+//!
+//! ```
+//! use coinbase_pro_rs::{mock::MockServer, Public, Sync};
+//! use hyper::Method;
+//!
+//! let server = MockServer::start();
+//! server.mock_json(Method::GET, "/products/BTC-USD/stats",
+//!     r#"{"open":"1.0","high":"2.0","low":"0.5","volume":"10.0"}"#);
+//!
+//! let client: Public<Sync> = Public::new(&server.uri());
+//! assert_eq!(2.0, client.get_stats24h("BTC-USD").unwrap().high);
+//! ```
+
+use futures::{SinkExt, StreamExt};
+use hyper::header::HeaderMap;
+use hyper::server::conn::Http;
+use hyper::service::service_fn;
+use hyper::{body::to_bytes, Body, Method, Request, Response, StatusCode};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::runtime::Runtime;
+use tokio::sync::broadcast;
+use tokio_tungstenite::{
+    accept_async,
+    tungstenite::{protocol::CloseFrame, Message as TMessage},
+};
+
+use crate::{private::Private, ASync};
+
+const FEED_CAPACITY: usize = 1024;
+
+const PRIVATE_PATHS: &[&str] = &[
+    "/accounts",
+    "/orders",
+    "/fills",
+    "/fees",
+    "/transfers",
+    "/users",
+    "/profiles",
+    "/deposits",
+    "/withdrawals",
+    "/payment-methods",
+    "/coinbase-accounts",
+    "/reports",
+];
+
+type Handler = Arc<dyn Fn(&MockRequest) -> MockResponse + Send + std::marker::Sync>;
+
+/// Request received by [`MockServer`]
+#[derive(Debug, Clone)]
+pub struct MockRequest {
+    pub method: Method,
+    pub path: String,
+    pub query: Option<String>,
+    pub headers: HeaderMap,
+    pub body: String,
+}
+
+/// Response returned by [`MockServer`] handlers
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+    pub status: StatusCode,
+    pub body: String,
+}
+
+impl MockResponse {
+    /// 200 OK with json body
+    pub fn json(body: &str) -> Self {
+        MockResponse {
+            status: StatusCode::OK,
+            body: body.to_string(),
+        }
+    }
+
+    /// Error in Coinbase format: `{"message": ...}`
+    pub fn error(status: StatusCode, message: &str) -> Self {
+        MockResponse {
+            status,
+            body: json!({ "message": message }).to_string(),
+        }
+    }
+}
+
+struct Credentials {
+    key: String,
+    secret: String,
+    passphrase: String,
+}
+
+#[derive(Default)]
+struct State {
+    credentials: Option<Credentials>,
+    routes: Vec<(Method, String, Handler)>,
+    requests: Vec<MockRequest>,
+    ws_script: Vec<String>,
+    ws_pongs: Vec<Vec<u8>>,
+}
+
+/// Local HTTP + websocket server emulating Coinbase api
+///
+/// Server runs on its own runtime, so it can be used from sync and async tests,
+/// and is stopped when dropped.
+pub struct MockServer {
+    http_addr: SocketAddr,
+    ws_addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    feed: broadcast::Sender<TMessage>,
+    rt: Option<Runtime>,
+}
+
+impl MockServer {
+    /// Starts server on random localhost ports
+    pub fn start() -> Self {
+        let rt = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .expect("mock runtime");
+
+        let http = Self::bind();
+        let ws = Self::bind();
+        let http_addr = http.local_addr().expect("mock http addr");
+        let ws_addr = ws.local_addr().expect("mock ws addr");
+
+        let state = Arc::new(Mutex::new(State::default()));
+        let (feed, _) = broadcast::channel(FEED_CAPACITY);
+
+        rt.spawn(serve_http(http, state.clone()));
+        rt.spawn(serve_ws(ws, state.clone(), feed.clone()));
+
+        let server = MockServer {
+            http_addr,
+            ws_addr,
+            state,
+            feed,
+            rt: Some(rt),
+        };
+        server.mock(Method::GET, "/time", |_| {
+            let now = chrono::Utc::now();
+            MockResponse::json(
+                &json!({
+                    "iso": now.to_rfc3339(),
+                    "epoch": now.timestamp_millis() as f64 / 1000.0
+                })
+                .to_string(),
+            )
+        });
+        server
+    }
+
+    fn bind() -> std::net::TcpListener {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("mock bind");
+        listener.set_nonblocking(true).expect("mock nonblocking");
+        listener
+    }
+
+    /// Requires `CB-ACCESS-*` headers on private endpoints and verifies their signature
+    /// (and websocket subscription signature) with the given api key
+    pub fn credentials(self, key: &str, secret: &str, passphrase: &str) -> Self {
+        self.state.lock().unwrap().credentials = Some(Credentials {
+            key: key.to_string(),
+            secret: secret.to_string(),
+            passphrase: passphrase.to_string(),
+        });
+        self
+    }
+
+    /// Uri for `Public::new`/`Private::new`
+    pub fn uri(&self) -> String {
+        format!("http://{}", self.http_addr)
+    }
+
+    /// Uri for `WSFeed::connect`
+    pub fn ws_uri(&self) -> String {
+        format!("ws://{}", self.ws_addr)
+    }
+
+    /// Scripted response: `handler` is called for every request to `path` (without query).
+    /// Later mocks of the same endpoint override earlier ones
+    pub fn mock<F>(&self, method: Method, path: &str, handler: F)
+    where
+        F: Fn(&MockRequest) -> MockResponse + Send + std::marker::Sync + 'static,
+    {
+        self.state
+            .lock()
+            .unwrap()
+            .routes
+            .push((method, path.to_string(), Arc::new(handler)));
+    }
+
+    /// Canned 200 OK json response
+    pub fn mock_json(&self, method: Method, path: &str, body: &str) {
+        let body = body.to_string();
+        self.mock(method, path, move |_| MockResponse::json(&body));
+    }
+
+    /// All requests received so far
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// Frame sent to every new websocket connection right after its first subscription
+    pub fn ws_script(&self, frame: &str) {
+        self.state.lock().unwrap().ws_script.push(frame.to_string());
+    }
+
+    /// Sends frame to all currently connected websockets
+    pub fn ws_send(&self, frame: &str) {
+        let _ = self.feed.send(TMessage::Text(frame.to_string()));
+    }
+
+    /// Sends ping frame to all currently connected websockets
+    pub fn ws_ping(&self, data: &[u8]) {
+        let _ = self.feed.send(TMessage::Ping(data.to_vec()));
+    }
+
+    /// Closes all currently connected websockets with the close frame
+    pub fn ws_close(&self, code: u16, reason: &str) {
+        let _ = self.feed.send(TMessage::Close(Some(CloseFrame {
+            code: code.into(),
+            reason: reason.to_string().into(),
+        })));
+    }
+
+    /// Payloads of all pong frames received so far
+    pub fn ws_pongs(&self) -> Vec<Vec<u8>> {
+        self.state.lock().unwrap().ws_pongs.clone()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        if let Some(rt) = self.rt.take() {
+            rt.shutdown_background();
+        }
+    }
+}
+
+/// Records the request and calls its handler without holding the lock,
+/// so handlers can use the server
+fn respond(state: &Mutex<State>, request: MockRequest) -> MockResponse {
+    let handler = {
+        let mut state = state.lock().unwrap();
+        state.requests.push(request.clone());
+        state.verify(&request).map(|()| state.route(&request))
+    };
+    match handler {
+        Ok(Some(handler)) => handler(&request),
+        Ok(None) => MockResponse::error(StatusCode::NOT_FOUND, "NotFound"),
+        Err(err) => err,
+    }
+}
+
+impl State {
+    fn route(&self, request: &MockRequest) -> Option<Handler> {
+        self.routes
+            .iter()
+            .rev()
+            .find(|(method, path, _)| *method == request.method && *path == request.path)
+            .map(|(_, _, handler)| handler.clone())
+    }
+
+    fn verify(&self, request: &MockRequest) -> Result<(), MockResponse> {
+        let credentials = match &self.credentials {
+            Some(credentials) => credentials,
+            None => return Ok(()),
+        };
+        let unauthorized = |message| Err(MockResponse::error(StatusCode::UNAUTHORIZED, message));
+        let header = |name| request.headers.get(name).and_then(|x| x.to_str().ok());
+
+        let key = match header("CB-ACCESS-KEY") {
+            Some(key) => key,
+            None if PRIVATE_PATHS.iter().any(|x| request.path.starts_with(x)) => {
+                return unauthorized("CB-ACCESS-KEY header is required")
+            }
+            None => return Ok(()),
+        };
+        if key != credentials.key {
+            return unauthorized("invalid api key");
+        }
+        if header("CB-ACCESS-PASSPHRASE") != Some(&credentials.passphrase) {
+            return unauthorized("invalid passphrase");
+        }
+        let timestamp = match header("CB-ACCESS-TIMESTAMP").and_then(|x| x.parse().ok()) {
+            Some(timestamp) => timestamp,
+            None => return unauthorized("invalid timestamp"),
+        };
+        let uri = match &request.query {
+            Some(query) => format!("{}?{}", request.path, query),
+            None => request.path.clone(),
+        };
+        let sign = Private::<ASync>::sign(
+            &credentials.secret,
+            timestamp,
+            request.method.clone(),
+            &uri,
+            &request.body,
+        );
+        if header("CB-ACCESS-SIGN") != Some(&sign) {
+            return unauthorized("invalid signature");
+        }
+        Ok(())
+    }
+
+    fn verify_ws(&self, cmd: &Value) -> Result<(), String> {
+        let (credentials, signature) = match (&self.credentials, cmd["signature"].as_str()) {
+            (Some(credentials), Some(signature)) => (credentials, signature),
+            _ => return Ok(()),
+        };
+        let sign = cmd["timestamp"]
+            .as_str()
+            .and_then(|x| x.parse().ok())
+            .map(|timestamp| {
+                Private::<ASync>::sign(
+                    &credentials.secret,
+                    timestamp,
+                    Method::GET,
+                    "/users/self/verify",
+                    "",
+                )
+            });
+        let valid = cmd["key"].as_str() == Some(&credentials.key)
+            && cmd["passphrase"].as_str() == Some(&credentials.passphrase)
+            && sign.as_deref() == Some(signature);
+        if valid {
+            Ok(())
+        } else {
+            Err(ws_error("Authentication Failed"))
+        }
+    }
+
+    fn on_ws_command(
+        &self,
+        text: &str,
+        subscriptions: &mut BTreeMap<String, Vec<String>>,
+    ) -> Result<String, String> {
+        let cmd: Value = serde_json::from_str(text).map_err(|_| ws_error("Failed to parse"))?;
+        let unsubscribe = match cmd["type"].as_str() {
+            Some("subscribe") => false,
+            Some("unsubscribe") => true,
+            _ => return Err(ws_error("Failed to subscribe")),
+        };
+        self.verify_ws(&cmd)?;
+
+        let product_ids = strings(&cmd["product_ids"]);
+        for channel in cmd["channels"].as_array().into_iter().flatten() {
+            let (name, ids) = match channel {
+                Value::String(name) => (name.clone(), product_ids.clone()),
+                Value::Object(_) => match channel["name"].as_str() {
+                    Some(name) => (name.to_string(), strings(&channel["product_ids"])),
+                    None => continue,
+                },
+                _ => continue,
+            };
+            let entry = subscriptions.entry(name).or_default();
+            if unsubscribe && ids.is_empty() {
+                entry.clear();
+            }
+            for id in ids {
+                if unsubscribe {
+                    entry.retain(|x| *x != id);
+                } else if !entry.contains(&id) {
+                    entry.push(id);
+                }
+            }
+        }
+        subscriptions.retain(|_, ids| !ids.is_empty());
+
+        let channels: Vec<_> = subscriptions
+            .iter()
+            .map(|(name, ids)| json!({ "name": name, "product_ids": ids }))
+            .collect();
+        Ok(json!({ "type": "subscriptions", "channels": channels }).to_string())
+    }
+}
+
+fn strings(value: &Value) -> Vec<String> {
+    value
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|x| x.as_str().map(|x| x.to_string()))
+        .collect()
+}
+
+fn ws_error(message: &str) -> String {
+    json!({ "type": "error", "message": message }).to_string()
+}
+
+async fn serve_http(listener: std::net::TcpListener, state: Arc<Mutex<State>>) {
+    let listener = TcpListener::from_std(listener).expect("mock http listener");
+    while let Ok((stream, _)) = listener.accept().await {
+        let state = state.clone();
+        tokio::spawn(async move {
+            let service = service_fn(move |req| handle_http(state.clone(), req));
+            let _ = Http::new().serve_connection(stream, service).await;
+        });
+    }
+}
+
+async fn handle_http(
+    state: Arc<Mutex<State>>,
+    req: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    let (parts, body) = req.into_parts();
+    let body = to_bytes(body)
+        .await
+        .map(|x| String::from_utf8_lossy(&x).into_owned())
+        .unwrap_or_default();
+    let request = MockRequest {
+        method: parts.method,
+        path: parts.uri.path().to_string(),
+        query: parts.uri.query().map(|x| x.to_string()),
+        headers: parts.headers,
+        body,
+    };
+    log::debug!("MOCK REQ: {:?}", request);
+
+    let response = respond(&state, request);
+    Ok(Response::builder()
+        .status(response.status)
+        .header("Content-Type", "application/json")
+        .body(Body::from(response.body))
+        .unwrap())
+}
+
+async fn serve_ws(
+    listener: std::net::TcpListener,
+    state: Arc<Mutex<State>>,
+    feed: broadcast::Sender<TMessage>,
+) {
+    let listener = TcpListener::from_std(listener).expect("mock ws listener");
+    while let Ok((stream, _)) = listener.accept().await {
+        tokio::spawn(handle_ws(stream, state.clone(), feed.subscribe()));
+    }
+}
+
+async fn handle_ws(
+    stream: TcpStream,
+    state: Arc<Mutex<State>>,
+    mut feed: broadcast::Receiver<TMessage>,
+) {
+    let mut ws = match accept_async(stream).await {
+        Ok(ws) => ws,
+        Err(_) => return,
+    };
+    let mut subscriptions = BTreeMap::new();
+    let mut scripted = false;
+
+    loop {
+        let frames = tokio::select! {
+            msg = ws.next() => match msg {
+                Some(Ok(TMessage::Text(text))) => {
+                    let state = state.lock().unwrap();
+                    match state.on_ws_command(&text, &mut subscriptions) {
+                        Ok(reply) if !scripted => {
+                            scripted = true;
+                            std::iter::once(reply)
+                                .chain(state.ws_script.clone())
+                                .map(TMessage::Text)
+                                .collect()
+                        }
+                        Ok(reply) | Err(reply) => vec![TMessage::Text(reply)],
+                    }
+                }
+                Some(Ok(TMessage::Pong(data))) => {
+                    state.lock().unwrap().ws_pongs.push(data);
+                    vec![]
+                }
+                Some(Ok(TMessage::Close(_))) | Some(Err(_)) | None => return,
+                Some(Ok(_)) => vec![],
+            },
+            frame = feed.recv() => match frame {
+                Ok(frame) => vec![frame],
+                Err(broadcast::error::RecvError::Lagged(_)) => vec![],
+                Err(broadcast::error::RecvError::Closed) => return,
+            },
+        };
+        for frame in frames {
+            if ws.send(frame).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::wsfeed::*;
+    use crate::{Public, Sync, WSFeed};
+
+    static KEY: &str = "9eaa4603717ffdc322771a933ae12501";
+    static SECRET: &str =
+        "RrLem7Ihmnn57ryW4Cc3Rp31h+Bm2DEPmzNbRiPrQQRE1yH6WNybmhK8xSqHjUNaR/V8huS+JMhBlr8PKt2GhQ==";
+    static PASSPHRASE: &str = "sandbox";
+
+    static ACCOUNTS: &str = r#"[{"id":"2fec40ac-525b-4192-871a-39d784945055","currency":"BTC",
+        "balance":"1.5","available":"1.0","hold":"0.5",
+        "profile_id":"fa94ac51-b20a-4b16-bc7a-af3c0abb7ec4","trading_enabled":true}]"#;
+
+    #[test]
+    fn test_get_time_sync() {
+        let server = MockServer::start();
+        let client: Public<Sync> = Public::new(&server.uri());
+        let time = client.get_time().unwrap();
+        assert!(time.epoch > 0.0);
+        assert_eq!("/time", server.requests()[0].path);
+    }
+
+    #[tokio::test]
+    async fn test_not_found() {
+        let server = MockServer::start();
+        let client: Public<ASync> = Public::new(&server.uri());
+        let err = client.get_products().await.unwrap_err();
+        assert!(err.to_string().contains("NotFound"));
+    }
+
+    #[tokio::test]
+    async fn test_scripted() {
+        let server = MockServer::start();
+        server.mock(Method::GET, "/products/BTC-USD/candles", |req| {
+            assert!(req.query.as_ref().unwrap().contains("granularity=60"));
+            MockResponse::json(
+                "[[1600000060,1.0,3.0,2.0,2.5,10.0],[1600000000,1.0,2.0,1.5,2.0,5.0]]",
+            )
+        });
+        let client: Public<ASync> = Public::new(&server.uri());
+        let candles = client
+            .get_candles(
+                "BTC-USD",
+                None,
+                None,
+                crate::structs::public::Granularity::M1,
+            )
+            .await
+            .unwrap();
+        assert_eq!(2, candles.len());
+        assert_eq!(1600000060, candles[0].time.timestamp());
+    }
+
+    #[tokio::test]
+    async fn test_handler_uses_server() {
+        let server = Arc::new(MockServer::start());
+        let weak = Arc::downgrade(&server);
+        server.mock(Method::GET, "/products/BTC-USD/stats", move |_| {
+            let server = weak.upgrade().unwrap();
+            server.mock_json(Method::GET, "/currencies", "[]");
+            let volume = server.requests().len();
+            MockResponse::json(&format!(
+                r#"{{"open":"1.0","high":"2.0","low":"0.5","volume":"{}"}}"#,
+                volume
+            ))
+        });
+        let client: Public<ASync> = Public::new(&server.uri());
+        let stats = client.get_stats24h("BTC-USD").await.unwrap();
+        assert_eq!(1.0, stats.volume);
+        assert!(client.get_currencies().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_signature() {
+        let server = MockServer::start().credentials(KEY, SECRET, PASSPHRASE);
+        server.mock_json(Method::GET, "/accounts", ACCOUNTS);
+
+        let client: Private<ASync> = Private::new(&server.uri(), KEY, SECRET, PASSPHRASE);
+        let accounts = client.get_accounts().await.unwrap();
+        assert_eq!("BTC", accounts[0].currency);
+        assert_eq!(
+            Some(KEY),
+            server.requests()[0]
+                .headers
+                .get("CB-ACCESS-KEY")
+                .and_then(|x| x.to_str().ok())
+        );
+
+        let wrong_secret = "dTUic8DZPqkS77vxhJFEX5IBr13FcFHTzWYOARgT9kDWGdN03uvxBbH/hVy8f4O5RDmuf+9wNpEfhYhw2FCWyA==";
+        let client: Private<ASync> = Private::new(&server.uri(), KEY, wrong_secret, PASSPHRASE);
+        let err = client.get_accounts().await.unwrap_err();
+        assert!(err.to_string().contains("invalid signature"));
+
+        let client: Public<ASync> = Public::new(&server.uri());
+        let err = client.get_time().await;
+        assert!(err.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_feed() {
+        let server = MockServer::start();
+        server.ws_script(
+            r#"{"type":"heartbeat","last_trade_id":20,"product_id":"BTC-USD","sequence":10,
+                "time":"2019-08-21T22:10:15.190000Z"}"#,
+        );
+
+        let mut stream = WSFeed::connect(&server.ws_uri(), &["BTC-USD"], &[ChannelType::Heartbeat])
+            .await
+            .unwrap();
+        assert_eq!(
+            Message::Subscriptions {
+                channels: vec![Channel::WithProduct {
+                    name: ChannelType::Heartbeat,
+                    product_ids: vec!["BTC-USD".to_string()]
+                }]
+            },
+            stream.next().await.unwrap().unwrap()
+        );
+        match stream.next().await.unwrap().unwrap() {
+            Message::Heartbeat { sequence, .. } => assert_eq!(10, sequence),
+            other => panic!("{:?}", other),
+        }
+
+        server.ws_send(r#"{"type":"error","message":"test"}"#);
+        assert_eq!(
+            Message::Error {
+                message: "test".to_string()
+            },
+            stream.next().await.unwrap().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_feed_auth() {
+        let server = MockServer::start().credentials(KEY, SECRET, PASSPHRASE);
+        let mut stream = WSFeed::connect_with_auth(
+            &server.ws_uri(),
+            &["BTC-USD"],
+            &[ChannelType::User],
+            KEY,
+            SECRET,
+            "wrong",
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            Message::Error {
+                message: "Authentication Failed".to_string()
+            },
+            stream.next().await.unwrap().unwrap()
+        );
+    }
+}
@@ -11,11 +11,13 @@ use uuid::Uuid;
 use crate::adapters::{Adapter, AdapterNew};
 use crate::error::*;
 use crate::structs::private::*;
+use crate::structs::public::{ParseProductIdError, ProductId};
 use crate::structs::{reqs, DateTime};
 use hmac::{Hmac, Mac, NewMac};
 use sha2::Sha256;
 
 use crate::public::Public;
+use crate::utils::encode;
 
 // Create alias for HMAC-SHA256
 type HmacSha256 = Hmac<Sha256>;
@@ -58,6 +60,12 @@ impl<A> Private<A> {
             .call(self.request(method, uri, body_str.to_string()))
     }
 
+    fn parse_product_id(
+        product_id: Option<&str>,
+    ) -> Result<Option<ProductId>, ParseProductIdError> {
+        product_id.map(str::parse).transpose()
+    }
+
     fn call_get<U>(&self, uri: &str) -> A::Result
     where
         A: Adapter<U> + 'static,
@@ -224,6 +232,9 @@ impl<A> Private<A> {
     where
         A: Adapter<Order> + 'static,
     {
+        if let Err(e) = order.product_id.parse::<ProductId>() {
+            return self._pub.fail(CBError::ProductId(e));
+        }
         let body_str = serde_json::to_string(&order).expect("cannot to_string post body");
 
         self.call(Method::POST, "/orders", &body_str)
@@ -334,8 +345,12 @@ impl<A> Private<A> {
     where
         A: Adapter<Vec<Uuid>> + 'static,
     {
+        let product_id = match Self::parse_product_id(product_id) {
+            Ok(product_id) => product_id,
+            Err(e) => return self._pub.fail(CBError::ProductId(e)),
+        };
         let param = product_id
-            .map(|x| format!("?product_id={}", x))
+            .map(|x| format!("?product_id={}", encode(&x)))
             .unwrap_or_default();
 
         self.call(Method::DELETE, &format!("/orders{}", param), "")
@@ -358,10 +373,14 @@ impl<A> Private<A> {
     where
         A: Adapter<Vec<Order>> + 'static,
     {
+        let product_id = match Self::parse_product_id(product_id) {
+            Ok(product_id) => product_id,
+            Err(e) => return self._pub.fail(CBError::ProductId(e)),
+        };
         // TODO rewrite
         let param_status = status.map(|x| format!("&status={}", x)).unwrap_or_default();
         let param_product = product_id
-            .map(|x| format!("&product_id={}", x))
+            .map(|x| format!("&product_id={}", encode(&x)))
             .unwrap_or_default();
         let mut param = (param_status + &param_product).into_bytes();
         if !param.is_empty() {
@@ -397,11 +416,15 @@ impl<A> Private<A> {
     where
         A: Adapter<Vec<Fill>> + 'static,
     {
+        let product_id = match Self::parse_product_id(product_id) {
+            Ok(product_id) => product_id,
+            Err(e) => return self._pub.fail(CBError::ProductId(e)),
+        };
         let param_order = order_id
             .map(|x| format!("&order_id={}", x))
             .unwrap_or_default();
         let param_product = product_id
-            .map(|x| format!("&product_id={}", x))
+            .map(|x| format!("&product_id={}", encode(&x)))
             .unwrap_or_default();
         let mut param = (param_order + &param_product).into_bytes();
         if !param.is_empty() {
@@ -562,6 +585,15 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_invalid_product_id() {
+        let client: Private<Sync> = Private::new(SANDBOX_URL, KEY, SECRET, PASSPHRASE);
+        let res = client.buy_limit("BTCUSD", 1.0, 1.12, true);
+        assert!(matches!(res, Err(CBError::ProductId(_))));
+        let res = client.get_orders(None, Some("BTC/USD"));
+        assert!(matches!(res, Err(CBError::ProductId(_))));
+    }
+
     #[test]
     #[serial]
     fn test_buy_market_funds() {
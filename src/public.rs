@@ -1,17 +1,23 @@
 //! Contains structure which provides access to Public section of Coinbase api
 
-use chrono::SecondsFormat;
+use chrono::{SecondsFormat, TimeZone, Utc};
+use futures::future;
 use hyper::client::HttpConnector;
 use hyper::{body::to_bytes, Body, Client, Request, Uri};
 use hyper_tls::HttpsConnector;
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::fmt::Debug;
 use std::future::Future;
+use std::time::Duration;
 
 use super::adapters::*;
 use crate::error::*;
 use crate::structs::public::*;
 use crate::structs::DateTime;
+use crate::utils::encode;
+
+pub use crate::structs::public::MAX_CANDLES;
 
 pub struct Public<Adapter> {
     pub(crate) uri: String,
@@ -39,22 +45,53 @@ impl<A> Public<A> {
         self.call(self.request(uri))
     }
 
+    /// Same as `get_pub` for product endpoints, invalid `product_id` fails without a request
+    fn get_product_pub<U>(&self, product_id: &str, uri: impl FnOnce(&str) -> String) -> A::Result
+    where
+        A: Adapter<U> + 'static,
+        U: Send + 'static,
+        for<'de> U: serde::Deserialize<'de>,
+    {
+        match product_id.parse::<ProductId>() {
+            Ok(product_id) => self.get_pub(&uri(&product_id)),
+            Err(e) => self.fail(CBError::ProductId(e)),
+        }
+    }
+
+    pub(crate) fn fail<U>(&self, err: CBError) -> A::Result
+    where
+        A: Adapter<U> + 'static,
+        U: Send + 'static,
+    {
+        self.adapter.process(future::ready(Err(err)))
+    }
+
     pub(crate) fn call_future<U>(
         &self,
         request: Request<Body>,
     ) -> impl Future<Output = Result<U, CBError>> + 'static
+    where
+        for<'de> U: serde::Deserialize<'de> + 'static,
+    {
+        Self::call_future_with(&self.client, request)
+    }
+
+    fn call_future_with<U>(
+        client: &Client<HttpsConnector<HttpConnector>>,
+        request: Request<Body>,
+    ) -> impl Future<Output = Result<U, CBError>> + 'static
     where
         for<'de> U: serde::Deserialize<'de> + 'static,
     {
         log::debug!("REQ: {:?}", request);
 
-        let res = self.client.request(request);
+        let res = client.request(request);
         async move {
             let res = res.await.map_err(CBError::Http)?;
             let body = to_bytes(res.into_body()).await.map_err(CBError::Http)?;
             log::debug!("RES: {:#?}", body);
-            let res: Result<U, CBError> = serde_json::from_slice(&body).map_err(|e| {
-                let err = serde_json::from_slice(&body);
+            let res: Result<U, CBError> = crate::json::from_slice(&body).map_err(|e| {
+                let err = crate::json::from_slice(&body);
                 let err = err.map(CBError::Coinbase).unwrap_or_else(|_| {
                     let data = String::from_utf8(body.to_vec()).unwrap();
                     CBError::Serde { error: e, data }
@@ -119,7 +156,7 @@ impl<A> Public<A> {
     where
         A: Adapter<Product> + 'static,
     {
-        self.get_pub(&format!("/products/{}", product_id))
+        self.get_product_pub(product_id, |x| format!("/products/{}", encode(x)))
     }
 
     pub fn get_book<T>(&self, product_id: &str) -> A::Result
@@ -129,25 +166,55 @@ impl<A> Public<A> {
         T: std::marker::Send,
         T: for<'de> Deserialize<'de>,
     {
-        self.get_pub(&format!(
-            "/products/{}/book?level={}",
-            product_id,
-            T::level()
-        ))
+        self.get_product_pub(product_id, |x| {
+            format!("/products/{}/book?level={}", encode(x), T::level())
+        })
     }
 
     pub fn get_ticker(&self, product_id: &str) -> A::Result
     where
         A: Adapter<Ticker> + 'static,
     {
-        self.get_pub(&format!("/products/{}/ticker", product_id))
+        self.get_product_pub(product_id, |x| format!("/products/{}/ticker", encode(x)))
     }
 
     pub fn get_trades(&self, product_id: &str) -> A::Result
     where
         A: Adapter<Vec<Trade>> + 'static,
     {
-        self.get_pub(&format!("/products/{}/trades", product_id))
+        self.get_product_pub(product_id, |x| format!("/products/{}/trades", encode(x)))
+    }
+
+    /// **Trades page**
+    ///
+    /// `before`/`after` are `trade_id` cursors: `after` returns older trades,
+    /// `before` newer ones. `limit` is up to 1000
+    pub fn get_trades_paginated(
+        &self,
+        product_id: &str,
+        before: Option<usize>,
+        after: Option<usize>,
+        limit: Option<usize>,
+    ) -> A::Result
+    where
+        A: Adapter<Vec<Trade>> + 'static,
+    {
+        let param_before = before.map(|x| format!("&before={}", x)).unwrap_or_default();
+        let param_after = after.map(|x| format!("&after={}", x)).unwrap_or_default();
+        let param_limit = limit.map(|x| format!("&limit={}", x)).unwrap_or_default();
+
+        let mut param = (param_before + &param_after + &param_limit).into_bytes();
+        if !param.is_empty() {
+            param[0] = b'?';
+        }
+
+        self.get_product_pub(product_id, |x| {
+            format!(
+                "/products/{}/trades{}",
+                encode(x),
+                String::from_utf8(param).unwrap()
+            )
+        })
     }
 
     pub fn get_candles(
@@ -160,6 +227,17 @@ impl<A> Public<A> {
     where
         A: Adapter<Vec<Candle>> + 'static,
     {
+        self.get_product_pub(product_id, |x| {
+            Self::candles_uri(x, start, end, granularity)
+        })
+    }
+
+    fn candles_uri(
+        product_id: &str,
+        start: Option<DateTime>,
+        end: Option<DateTime>,
+        granularity: Granularity,
+    ) -> String {
         let param_start = start
             .map(|x| format!("&start={}", x.to_rfc3339_opts(SecondsFormat::Secs, true)))
             .unwrap_or_default();
@@ -167,18 +245,76 @@ impl<A> Public<A> {
             .map(|x| format!("&end={}", x.to_rfc3339_opts(SecondsFormat::Secs, true)))
             .unwrap_or_default();
 
-        let req = format!(
+        format!(
             "/products/{}/candles?granularity={}{}{}",
-            product_id, granularity as usize, param_start, param_end
-        );
-        self.get_pub(&req)
+            encode(product_id),
+            granularity.as_secs(),
+            param_start,
+            param_end
+        )
+    }
+
+    /// **Historic rates for arbitrary range**
+    ///
+    /// `get_candles` returns at most 300 buckets per call, so the range is split into
+    /// compliant windows which are requested one by one with pause between calls to respect
+    /// public rate limits. Result is sorted by time (oldest first) without duplicates, buckets
+    /// without trades are listed in `CandleHistory::missing`.
+    pub fn get_candles_range(
+        &self,
+        product_id: &str,
+        start: DateTime,
+        end: DateTime,
+        granularity: Granularity,
+    ) -> A::Result
+    where
+        A: Adapter<CandleHistory> + 'static,
+    {
+        let product_id: ProductId = match product_id.parse() {
+            Ok(product_id) => product_id,
+            Err(e) => return self.fail(CBError::ProductId(e)),
+        };
+        let client = self.client.clone();
+        let requests: Vec<_> = candle_windows(start, end, granularity)
+            .into_iter()
+            .map(|(start, end)| {
+                self.request(&Self::candles_uri(
+                    &product_id,
+                    Some(start),
+                    Some(end),
+                    granularity,
+                ))
+            })
+            .collect();
+
+        let f = async move {
+            let mut candles = vec![];
+            for (i, request) in requests.into_iter().enumerate() {
+                if i > 0 {
+                    tokio::time::sleep(CANDLES_REQUEST_INTERVAL).await;
+                }
+                let mut window: Vec<Candle> = Self::call_future_with(&client, request).await?;
+                candles.append(&mut window);
+            }
+            Ok(CandleHistory::new(candles, start, end, granularity))
+        };
+
+        self.adapter.process(f)
     }
 
     pub fn get_stats24h(&self, product_id: &str) -> A::Result
     where
         A: Adapter<Stats24H> + 'static,
     {
-        self.get_pub(&format!("/products/{}/stats", product_id))
+        self.get_product_pub(product_id, |x| format!("/products/{}/stats", encode(x)))
+    }
+
+    /// **24h and 30 day stats for all products**, keyed by product id
+    pub fn get_all_stats(&self) -> A::Result
+    where
+        A: Adapter<HashMap<String, ProductStats>> + 'static,
+    {
+        self.get_pub("/products/stats")
     }
 
     pub fn get_currencies(&self) -> A::Result
@@ -187,13 +323,43 @@ impl<A> Public<A> {
     {
         self.get_pub("/currencies")
     }
+
+    pub fn get_currency(&self, currency_id: &str) -> A::Result
+    where
+        A: Adapter<Currency> + 'static,
+    {
+        self.get_pub(&format!("/currencies/{}", encode(currency_id)))
+    }
+}
+
+/// Pause between requests of `get_candles_range`, public endpoints allow 3 requests per second
+const CANDLES_REQUEST_INTERVAL: Duration = Duration::from_millis(350);
+
+/// Splits range into windows of at most `MAX_CANDLES` buckets aligned to granularity
+fn candle_windows(
+    start: DateTime,
+    end: DateTime,
+    granularity: Granularity,
+) -> Vec<(DateTime, DateTime)> {
+    let step = granularity.as_secs();
+    let end = end.timestamp();
+    let mut from = start.timestamp().div_euclid(step) * step;
+    let mut windows = vec![];
+    while from <= end {
+        let to = end.min(from + (MAX_CANDLES as i64 - 1) * step);
+        windows.push((
+            Utc.timestamp_opt(from, 0).unwrap(),
+            Utc.timestamp_opt(to, 0).unwrap(),
+        ));
+        from = to + step;
+    }
+    windows
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::*;
-    use chrono::prelude::*;
     use std::time::Instant;
 
     static DELAY_TIMEOUT: u64 = 200;
@@ -280,6 +446,47 @@ mod tests {
         assert!(str.starts_with("[Trade { time: "));
     }
 
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_product_id_mock() {
+        use crate::mock::MockServer;
+
+        let server = MockServer::start();
+        server.mock_json(
+            hyper::Method::GET,
+            "/products/BTC-USD/stats",
+            r#"{"open":"1","high":"2","low":"1","volume":"5"}"#,
+        );
+        let client: Public<ASync> = Public::new(&server.uri());
+        let product_id = ProductId::new("BTC", "USD").unwrap();
+        let stats = client.get_stats24h(&product_id).await.unwrap();
+        assert_eq!(5.0, stats.volume);
+        let res = client.get_stats24h("BTC/USD").await;
+        assert!(matches!(res, Err(CBError::ProductId(_))));
+        assert_eq!(1, server.requests().len());
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_get_trades_paginated_mock() {
+        use crate::mock::MockServer;
+
+        let server = MockServer::start();
+        server.mock_json(
+            hyper::Method::GET,
+            "/products/BTC-USD/trades",
+            r#"[{"time":"2020-01-01T00:00:00.1Z","trade_id":99,"price":"10.0","size":"0.1","side":"buy"}]"#,
+        );
+        let client: Public<ASync> = Public::new(&server.uri());
+        let trades = client
+            .get_trades_paginated("BTC-USD", None, Some(100), Some(1))
+            .await
+            .unwrap();
+        assert_eq!(99, trades[0].trade_id);
+        let requests = server.requests();
+        assert_eq!(Some("after=100&limit=1"), requests[0].query.as_deref());
+    }
+
     #[test]
     #[serial]
     fn test_get_candles() {
@@ -292,7 +499,87 @@ mod tests {
             .unwrap();
         // let str = format!("{:?}", candles);
         // println!("{}", str);
-        assert!(candles[0].0 > candles[1].0);
+        assert!(candles[0].time > candles[1].time);
+    }
+
+    #[test]
+    fn test_candle_windows() {
+        let start = Utc.timestamp_opt(1_600_000_030, 0).unwrap();
+        let end = start + chrono::Duration::minutes(1000);
+        let windows = candle_windows(start, end, Granularity::M1);
+        assert_eq!(4, windows.len());
+        assert_eq!(1_600_000_020, windows[0].0.timestamp());
+        assert_eq!(end, windows[3].1);
+        for (from, to) in &windows {
+            assert!((*to - *from).num_minutes() < MAX_CANDLES as i64);
+        }
+        for pair in windows.windows(2) {
+            assert_eq!(60, (pair[1].0 - pair[0].1).num_seconds());
+        }
+    }
+
+    #[test]
+    fn test_candle_history() {
+        let t = |x| Utc.timestamp_opt(x, 0).unwrap();
+        let candle = |x, open, close| Candle::new(t(x), open, 2.0, 1.0, close, 1.0);
+        let candles = vec![
+            candle(900, 1.5, 1.8),
+            candle(660, 1.0, 1.2),
+            candle(900, 1.5, 1.8),
+            candle(720, 1.2, 1.3),
+            candle(540, 1.0, 1.0),
+        ];
+        let history = CandleHistory::new(candles, t(600), t(960), Granularity::M1);
+        assert_eq!(
+            vec![t(660), t(720), t(900)],
+            history.candles.iter().map(|x| x.time).collect::<Vec<_>>()
+        );
+        assert_eq!(vec![t(600), t(780), t(840), t(960)], history.missing);
+
+        let filled = history.filled();
+        assert_eq!(
+            vec![t(660), t(720), t(780), t(840), t(900), t(960)],
+            filled.iter().map(|x| x.time).collect::<Vec<_>>()
+        );
+        assert_eq!(Candle::new(t(780), 1.3, 1.3, 1.3, 1.3, 0.0), filled[2]);
+        assert_eq!(Candle::new(t(960), 1.8, 1.8, 1.8, 1.8, 0.0), filled[5]);
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_get_candles_range_mock() {
+        use crate::mock::{MockResponse, MockServer};
+
+        let server = MockServer::start();
+        server.mock(hyper::Method::GET, "/products/BTC-USD/candles", |req| {
+            let param = |name: &str| -> i64 {
+                let query = req.query.as_ref().unwrap();
+                let value = query.split('&').find_map(|x| x.strip_prefix(name)).unwrap();
+                value.parse::<structs::DateTime>().unwrap().timestamp()
+            };
+            let (start, end) = (param("start="), param("end="));
+            assert!((end - start) / 60 <= MAX_CANDLES as i64);
+            let mut candles: Vec<_> = (start..=end)
+                .step_by(60)
+                .filter(|x| x % 600 != 0)
+                .map(|x| serde_json::json!([x, 1.0, 2.0, 1.5, 1.5, 1.0]))
+                .collect();
+            candles.reverse();
+            MockResponse::json(&serde_json::Value::from(candles).to_string())
+        });
+
+        let client: Public<ASync> = Public::new(&server.uri());
+        let start = Utc.timestamp_opt(1_600_000_200, 0).unwrap();
+        let end = start + chrono::Duration::minutes(700);
+        let history = client
+            .get_candles_range("BTC-USD", start, end, Granularity::M1)
+            .await
+            .unwrap();
+
+        assert_eq!(3, server.requests().len());
+        assert_eq!(701 - 71, history.candles.len());
+        assert_eq!(71, history.missing.len());
+        assert!(history.candles.windows(2).all(|x| x[0].time < x[1].time));
     }
 
     #[test]
@@ -308,6 +595,33 @@ mod tests {
         assert!(str.contains("volume:"));
     }
 
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_get_all_stats_mock() {
+        use crate::mock::MockServer;
+
+        let server = MockServer::start();
+        server.mock_json(
+            hyper::Method::GET,
+            "/products/stats",
+            r#"{"BTC-USD":{"stats_30day":{"volume":"1000.5"},"stats_24hour":{"open":"10","high":"12","low":"9","last":"11","volume":"30"}}}"#,
+        );
+        let client: Public<ASync> = Public::new(&server.uri());
+        let stats = client.get_all_stats().await.unwrap();
+        let btc = &stats["BTC-USD"];
+        assert_eq!(Some(11.0), btc.stats_24hour.last);
+        assert_eq!(1000.5, btc.stats_30day.volume);
+    }
+
+    #[test]
+    #[serial]
+    fn test_get_currency() {
+        delay();
+        let client: Public<Sync> = Public::new(SANDBOX_URL);
+        let currency = client.get_currency("BTC").unwrap();
+        assert_eq!("Bitcoin", currency.name);
+    }
+
     #[test]
     #[serial]
     fn test_get_currencies() {
@@ -318,12 +632,12 @@ mod tests {
         let currency = currencies.iter().find(|x| x.id == "BTC").unwrap();
         assert_eq!(
             format!("{:?}", currency),
-            "Currency { id: \"BTC\", name: \"Bitcoin\", min_size: 1e-8, status: \"online\", message: Some(\"\"), max_precision: 1e-8, convertible_to: Some([]), details: CurrencyDetails { _type: Some(Crypto), symbol: Some(\"\"), network_confirmations: Some(2), sort_order: Some(3), crypto_address_link: Some(\"https://live.blockcypher.com/btc/address/{{address}}\") } }"
+            "Currency { id: \"BTC\", name: \"Bitcoin\", min_size: 1e-8, status: Online, message: Some(\"\"), max_precision: 1e-8, convertible_to: Some([]), details: CurrencyDetails { _type: Some(Crypto), symbol: Some(\"\"), network_confirmations: Some(2), sort_order: Some(3), crypto_address_link: Some(\"https://live.blockcypher.com/btc/address/{{address}}\") } }"
         );
         let currency = currencies.iter().find(|x| x.id == "USD").unwrap();
         assert_eq!(
             format!("{:?}", currency),
-            "Currency { id: \"USD\", name: \"United States Dollar\", min_size: 0.01, status: \"online\", message: Some(\"\"), max_precision: 0.01, convertible_to: Some([\"USDC\"]), details: CurrencyDetails { _type: Some(Fiat), symbol: Some(\"$\"), network_confirmations: None, sort_order: Some(0), crypto_address_link: None } }"
+            "Currency { id: \"USD\", name: \"United States Dollar\", min_size: 0.01, status: Online, message: Some(\"\"), max_precision: 0.01, convertible_to: Some([\"USDC\"]), details: CurrencyDetails { _type: Some(Fiat), symbol: Some(\"$\"), network_confirmations: None, sort_order: Some(0), crypto_address_link: None } }"
         );
     }
 
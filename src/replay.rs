@@ -0,0 +1,245 @@
+//! Contains recording of websocket-feed frames and their replay as futures::Stream
+//!
+//! Every received frame is stored as one json line together with local receive time.
+//! [`replay`] plays such recording through the same deserialization as [`WSFeed`](crate::WSFeed),
+//! so order books and strategies get exactly the same `Message` values as in the live session.
+//!
+//! ```no_run
+//! use coinbase_pro_rs::replay::{replay, ReplaySpeed};
+//! use futures::StreamExt;
+//! use tokio::{fs::File, io::BufReader};
+//!
+//! # async fn run() {
+//! let file = File::open("btc-usd.jsonl").await.unwrap();
+//! let mut stream = replay(BufReader::new(file), ReplaySpeed::Accelerated(10.0));
+//! while let Some(msg) = stream.next().await {
+//!     println!("{:?}", msg);
+//! }
+//! # }
+//! ```
+
+use chrono::Utc;
+use futures::stream;
+use serde::{Deserialize, Serialize};
+use std::io::{self, Write};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+use tokio_tungstenite::tungstenite::Message as TMessage;
+
+use crate::structs::DateTime;
+use crate::wsfeed::{convert_msg, CBStream};
+use crate::CBError;
+
+/// One line of recording
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RecordedFrame {
+    /// local receive time
+    pub time: DateTime,
+    /// raw websocket text frame
+    pub frame: String,
+}
+
+/// Writes received frames as json lines, used by `WSFeed::connect_with_sub_recorded`
+pub struct Recorder<W> {
+    writer: W,
+}
+
+impl<W: Write> Recorder<W> {
+    pub fn new(writer: W) -> Self {
+        Recorder { writer }
+    }
+
+    /// Records frame with current time as receive time
+    pub fn record(&mut self, frame: &str) -> io::Result<()> {
+        self.record_at(Utc::now(), frame)
+    }
+
+    pub fn record_at(&mut self, time: DateTime, frame: &str) -> io::Result<()> {
+        let line = serde_json::to_string(&RecordedFrame {
+            time,
+            frame: frame.to_string(),
+        })
+        .expect("cannot to_string recorded frame");
+        writeln!(self.writer, "{}", line)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Pace of [`replay`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplaySpeed {
+    /// Same delays between frames as in recording
+    Original,
+    /// Delays are divided by the factor, eg: `Accelerated(10.0)` is ten times faster.
+    /// Factor which is not positive plays as `Max`
+    Accelerated(f64),
+    /// No delays, as fast as possible
+    Max,
+}
+
+impl ReplaySpeed {
+    fn factor(self) -> Option<f64> {
+        match self {
+            ReplaySpeed::Original => Some(1.0),
+            ReplaySpeed::Accelerated(factor) if factor > 0.0 => Some(factor),
+            ReplaySpeed::Accelerated(_) | ReplaySpeed::Max => None,
+        }
+    }
+}
+
+/// Plays recording back as websocket-feed stream.
+///
+/// Broken lines are returned as `Err` and replay continues with the next line.
+pub fn replay<R>(reader: R, speed: ReplaySpeed) -> impl CBStream
+where
+    R: AsyncBufRead + Send + Unpin + 'static,
+{
+    let state = (reader.lines(), None::<DateTime>);
+
+    Box::pin(stream::unfold(state, move |(mut lines, prev)| async move {
+        let line = loop {
+            match lines.next_line().await.transpose()? {
+                Ok(line) if line.trim().is_empty() => continue,
+                Ok(line) => break line,
+                Err(e) => return Some((Err(CBError::Io(e)), (lines, prev))),
+            }
+        };
+        let frame: RecordedFrame = match serde_json::from_str(&line) {
+            Ok(frame) => frame,
+            Err(error) => {
+                let err = CBError::Serde { error, data: line };
+                return Some((Err(err), (lines, prev)));
+            }
+        };
+
+        if let (Some(prev), Some(factor)) = (prev, speed.factor()) {
+            let delay = (frame.time - prev).to_std().unwrap_or_default();
+            tokio::time::sleep(delay.div_f64(factor)).await;
+        }
+
+        let time = frame.time;
+        let msg = convert_msg(TMessage::Text(frame.frame));
+        Some((Ok(msg), (lines, Some(time))))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::wsfeed::*;
+    use chrono::Duration;
+    use futures::{StreamExt, TryStreamExt};
+    use std::io::Cursor;
+    use std::time::Instant;
+
+    static HEARTBEAT: &str = r#"{"type":"heartbeat","last_trade_id":20,"product_id":"BTC-USD","sequence":10,"time":"2019-08-21T22:10:15.190000Z"}"#;
+    static ERROR: &str = r#"{"type":"error","message":"test"}"#;
+
+    fn recording(step: Duration) -> Vec<u8> {
+        let start: DateTime = "2019-08-21T22:10:15Z".parse().unwrap();
+        let mut recorder = Recorder::new(vec![]);
+        recorder.record_at(start, HEARTBEAT).unwrap();
+        recorder.record_at(start + step, ERROR).unwrap();
+        recorder.into_inner()
+    }
+
+    #[tokio::test]
+    async fn test_replay_max() {
+        let data = recording(Duration::hours(1));
+        let msgs: Vec<_> = replay(Cursor::new(data), ReplaySpeed::Max)
+            .try_collect()
+            .await
+            .unwrap();
+
+        assert_eq!(2, msgs.len());
+        match &msgs[0] {
+            Message::Heartbeat { sequence, .. } => assert_eq!(10, *sequence),
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(
+            Message::Error {
+                message: "test".to_string()
+            },
+            msgs[1]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_replay_accelerated() {
+        let data = recording(Duration::seconds(1));
+        let time = Instant::now();
+        let msgs: Vec<_> = replay(Cursor::new(data), ReplaySpeed::Accelerated(20.0))
+            .collect()
+            .await;
+        assert_eq!(2, msgs.len());
+        assert!(time.elapsed() >= std::time::Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn test_replay_broken_line() {
+        let mut data = b"not json\n\n".to_vec();
+        data.extend(recording(Duration::zero()));
+        let msgs: Vec<_> = replay(Cursor::new(data), ReplaySpeed::Original)
+            .collect()
+            .await;
+        assert_eq!(3, msgs.len());
+        assert!(matches!(msgs[0], Err(CBError::Serde { .. })));
+        assert!(msgs[1].is_ok());
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_record() {
+        use crate::{mock::MockServer, WSFeed};
+        use std::sync::{Arc, Mutex};
+
+        #[derive(Clone, Default)]
+        struct Shared(Arc<Mutex<Vec<u8>>>);
+
+        impl Write for Shared {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let server = MockServer::start();
+        server.ws_script(HEARTBEAT);
+        let subscribe = Subscribe {
+            _type: SubscribeCmd::Subscribe,
+            product_ids: vec!["BTC-USD".to_string()],
+            channels: vec![Channel::Name(ChannelType::Heartbeat)],
+            auth: None,
+        };
+        let buf = Shared::default();
+        let mut stream =
+            WSFeed::connect_with_sub_recorded(&server.ws_uri(), subscribe, buf.clone())
+                .await
+                .unwrap();
+        let live: Vec<_> = (&mut stream).take(2).try_collect().await.unwrap();
+        server.ws_close(1000, "");
+        assert!(matches!(
+            stream.next().await,
+            Some(Err(CBError::Websocket(crate::WSError::Closed(_))))
+        ));
+
+        // frames are written by the recorder thread
+        let mut data = vec![];
+        for _ in 0..100 {
+            data = buf.0.lock().unwrap().clone();
+            if data.iter().filter(|x| **x == b'\n').count() == 2 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        let replayed: Vec<_> = replay(Cursor::new(data), ReplaySpeed::Max)
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(live, replayed);
+    }
+}
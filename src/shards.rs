@@ -0,0 +1,351 @@
+//! Contains feed spreading products across several websocket connections
+//!
+//! One connection subscribed to every product on `full` or `level2` can't keep up and Coinbase
+//! drops it. [`ShardedFeed`] splits products evenly into connections of at most
+//! [`products_per_connection`](ShardedFeed::products_per_connection) products and merges their
+//! messages into one stream. When a connection drops, its error is passed to the stream, its
+//! products are moved to connections with spare capacity and the rest are resubscribed on a new
+//! connection after the [`reconnect`](ShardedFeed::reconnect) delay.
+//!
+//! ```no_run
+//! use coinbase_pro_rs::{shards::ShardedFeed, structs::wsfeed::ChannelType, WS_URL};
+//! use futures::StreamExt;
+//!
+//! # async fn run(product_ids: Vec<String>) {
+//! let mut stream = ShardedFeed::new(WS_URL, &[ChannelType::Level2])
+//!     .products_per_connection(10)
+//!     .connect(&product_ids)
+//!     .await
+//!     .unwrap();
+//! while let Some(msg) = stream.next().await {
+//!     println!("{:?}", msg);
+//! }
+//! # }
+//! ```
+
+use futures::{future, stream, StreamExt};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+use crate::structs::wsfeed::{Channel, ChannelType, Message, Subscribe, SubscribeCmd};
+use crate::wsfeed::{CBSink, CBStream};
+use crate::{CBError, WSFeed};
+
+const PRODUCTS_PER_CONNECTION: usize = 20;
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const CAPACITY: usize = 1024;
+
+/// Builder of the sharded feed
+#[derive(Clone, Debug)]
+pub struct ShardedFeed {
+    uri: String,
+    channels: Vec<ChannelType>,
+    limit: usize,
+    delay: Duration,
+}
+
+impl ShardedFeed {
+    pub fn new(uri: &str, channels: &[ChannelType]) -> Self {
+        ShardedFeed {
+            uri: uri.to_string(),
+            channels: channels.to_vec(),
+            limit: PRODUCTS_PER_CONNECTION,
+            delay: RECONNECT_DELAY,
+        }
+    }
+
+    /// Maximum number of products of one connection, 20 by default
+    pub fn products_per_connection(mut self, limit: usize) -> Self {
+        self.limit = limit.max(1);
+        self
+    }
+
+    /// Delay before reconnecting a dropped connection, 1 second by default
+    pub fn reconnect(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Opens all connections, fails if any of them fails or with `CBError::Null` if there
+    /// are no products
+    pub async fn connect(self, product_ids: &[impl AsRef<str>]) -> Result<impl CBStream, CBError> {
+        if product_ids.is_empty() {
+            return Err(CBError::Null);
+        }
+        let products: Vec<_> = product_ids.iter().map(|x| x.as_ref().to_string()).collect();
+        let groups = shard(&products, self.limit);
+        let conns = future::try_join_all(
+            groups
+                .iter()
+                .map(|x| WSFeed::connect_with_sub(&self.uri, self.subscribe(x))),
+        )
+        .await?;
+
+        let (out, rx) = mpsc::channel(CAPACITY);
+        let (events, events_rx) = mpsc::unbounded_channel();
+        let shards = groups
+            .into_iter()
+            .zip(conns)
+            .enumerate()
+            .map(|(idx, (products, conn))| {
+                let (tx, cmds) = mpsc::unbounded_channel();
+                let feed = self.clone();
+                let (out, events) = (out.clone(), events.clone());
+                tokio::spawn(async move { feed.run_shard(idx, conn, cmds, out, events).await });
+                Shard {
+                    products,
+                    cmds: Some(tx),
+                }
+            })
+            .collect();
+        tokio::spawn(self.manage(shards, events, events_rx, out));
+
+        let stream = stream::unfold(rx, |mut rx| async move {
+            let msg = rx.recv().await?;
+            Some((msg, rx))
+        });
+        Ok(stream.boxed())
+    }
+
+    fn subscribe(&self, product_ids: &[String]) -> Subscribe {
+        Subscribe {
+            _type: SubscribeCmd::Subscribe,
+            product_ids: product_ids.to_vec(),
+            channels: self.channels.iter().cloned().map(Channel::Name).collect(),
+            auth: None,
+        }
+    }
+
+    /// Reconnects shards reported in `events` and moves their products
+    async fn manage(
+        self,
+        mut shards: Vec<Shard>,
+        events: mpsc::UnboundedSender<usize>,
+        mut events_rx: mpsc::UnboundedReceiver<usize>,
+        out: mpsc::Sender<Result<Message, CBError>>,
+    ) {
+        loop {
+            let idx = tokio::select! {
+                idx = events_rx.recv() => match idx {
+                    Some(idx) => idx,
+                    None => return,
+                },
+                _ = out.closed() => return,
+            };
+            shards[idx].cmds = None;
+            let (moves, mut rest) = rebalance(&mut shards, idx, self.limit);
+            for (to, products) in moves {
+                let sent = shards[to]
+                    .cmds
+                    .as_ref()
+                    .map(|x| x.send(products.clone()).is_ok())
+                    .unwrap_or(false);
+                if sent {
+                    log::info!("moving {:?} to connection {}", products, to);
+                } else {
+                    shards[to].products.retain(|x| !products.contains(x));
+                    rest.extend(products);
+                }
+            }
+            if rest.is_empty() {
+                continue;
+            }
+            log::info!("reconnecting {:?} on connection {}", rest, idx);
+            let (tx, cmds) = mpsc::unbounded_channel();
+            shards[idx] = Shard {
+                products: rest.clone(),
+                cmds: Some(tx),
+            };
+            let feed = self.clone();
+            let (out, events) = (out.clone(), events.clone());
+            tokio::spawn(async move {
+                tokio::time::sleep(feed.delay).await;
+                match WSFeed::connect_with_sub(&feed.uri, feed.subscribe(&rest)).await {
+                    Ok(conn) => feed.run_shard(idx, conn, cmds, out, events).await,
+                    Err(e) => {
+                        let _ = out.send(Err(e)).await;
+                        let _ = events.send(idx);
+                    }
+                }
+            });
+        }
+    }
+
+    /// Forwards messages of one connection, subscribes products received in `cmds`
+    async fn run_shard<S>(
+        self,
+        idx: usize,
+        mut conn: S,
+        mut cmds: mpsc::UnboundedReceiver<Vec<String>>,
+        out: mpsc::Sender<Result<Message, CBError>>,
+        events: mpsc::UnboundedSender<usize>,
+    ) where
+        S: CBStream + CBSink,
+    {
+        loop {
+            tokio::select! {
+                products = cmds.recv() => match products {
+                    Some(products) => {
+                        let products: Vec<&str> = products.iter().map(String::as_str).collect();
+                        if let Err(e) = conn.subscribe(&products, &self.channels, None).await {
+                            let _ = out.send(Err(e)).await;
+                            break;
+                        }
+                    }
+                    None => return,
+                },
+                msg = conn.next() => match msg {
+                    Some(Ok(msg)) => {
+                        if out.send(Ok(msg)).await.is_err() {
+                            return;
+                        }
+                    }
+                    Some(Err(e)) => {
+                        let _ = out.send(Err(e)).await;
+                        break;
+                    }
+                    None => break,
+                },
+            }
+        }
+        // products not subscribed yet are still listed by the manager and moved with the rest
+        drop(cmds);
+        let _ = events.send(idx);
+    }
+}
+
+struct Shard {
+    products: Vec<String>,
+    /// `None` while the connection is down
+    cmds: Option<mpsc::UnboundedSender<Vec<String>>>,
+}
+
+/// Splits products evenly into the least number of groups of at most `limit` products
+pub fn shard(product_ids: &[String], limit: usize) -> Vec<Vec<String>> {
+    if product_ids.is_empty() {
+        return vec![];
+    }
+    let limit = limit.max(1);
+    let count = product_ids.len().div_ceil(limit);
+    let size = product_ids.len().div_ceil(count);
+    product_ids.chunks(size).map(|x| x.to_vec()).collect()
+}
+
+/// Moves products of the dropped shard `idx` to live shards with spare capacity,
+/// returns the moves and the products left for reconnection
+fn rebalance(
+    shards: &mut [Shard],
+    idx: usize,
+    limit: usize,
+) -> (Vec<(usize, Vec<String>)>, Vec<String>) {
+    let mut rest = std::mem::take(&mut shards[idx].products);
+    let mut moves = vec![];
+    for (to, shard) in shards.iter_mut().enumerate() {
+        let spare = limit.saturating_sub(shard.products.len()).min(rest.len());
+        if shard.cmds.is_none() || spare == 0 {
+            continue;
+        }
+        let products: Vec<_> = rest.drain(..spare).collect();
+        shard.products.extend(products.iter().cloned());
+        moves.push((to, products));
+    }
+    (moves, rest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|x| x.to_string()).collect()
+    }
+
+    #[test]
+    fn test_shard() {
+        let products = ids(&["A", "B", "C", "D", "E"]);
+        assert_eq!(
+            vec![ids(&["A", "B", "C"]), ids(&["D", "E"])],
+            shard(&products, 4)
+        );
+        assert_eq!(vec![products.clone()], shard(&products, 5));
+        assert_eq!(5, shard(&products, 1).len());
+        assert!(shard(&[], 4).is_empty());
+    }
+
+    #[test]
+    fn test_rebalance() {
+        let (tx, _) = mpsc::unbounded_channel();
+        let mut shards = vec![
+            Shard {
+                products: ids(&["A", "B", "C"]),
+                cmds: None,
+            },
+            Shard {
+                products: ids(&["D", "E"]),
+                cmds: Some(tx.clone()),
+            },
+            Shard {
+                products: ids(&["F"]),
+                cmds: None,
+            },
+        ];
+        let (moves, rest) = rebalance(&mut shards, 0, 3);
+        assert_eq!(vec![(1, ids(&["A"]))], moves);
+        assert_eq!(ids(&["B", "C"]), rest);
+        assert_eq!(ids(&["D", "E", "A"]), shards[1].products);
+    }
+
+    #[tokio::test]
+    async fn test_no_products() {
+        let res = ShardedFeed::new("ws://localhost:1", &[ChannelType::Heartbeat])
+            .connect(&ids(&[]))
+            .await;
+        assert!(matches!(res, Err(CBError::Null)));
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_sharded_feed() {
+        use crate::mock::MockServer;
+
+        let server = MockServer::start();
+        let mut stream = ShardedFeed::new(&server.ws_uri(), &[ChannelType::Heartbeat])
+            .products_per_connection(2)
+            .reconnect(Duration::from_millis(10))
+            .connect(&["BTC-USD", "ETH-USD", "LTC-USD"])
+            .await
+            .unwrap();
+
+        let mut subscribed = vec![];
+        for _ in 0..2 {
+            match stream.next().await {
+                Some(Ok(Message::Subscriptions { channels })) => subscribed.push(channels),
+                other => panic!("{:?}", other),
+            }
+        }
+        subscribed.sort_by_key(|x| format!("{:?}", x));
+        assert_eq!(
+            vec![
+                vec![Channel::WithProduct {
+                    name: ChannelType::Heartbeat,
+                    product_ids: ids(&["BTC-USD", "ETH-USD"]),
+                }],
+                vec![Channel::WithProduct {
+                    name: ChannelType::Heartbeat,
+                    product_ids: ids(&["LTC-USD"]),
+                }],
+            ],
+            subscribed
+        );
+
+        server.ws_close(1000, "");
+        let (mut closed, mut resubscribed) = (0, 0);
+        while closed < 2 || resubscribed < 2 {
+            match stream.next().await {
+                Some(Err(_)) => closed += 1,
+                Some(Ok(Message::Subscriptions { .. })) => resubscribed += 1,
+                other => panic!("{:?}", other),
+            }
+        }
+    }
+}
@@ -4,7 +4,7 @@ use crate::utils::{
     datetime_from_string, datetime_with_tz_from_string, f64_from_string, f64_opt_from_string,
     option_datetime_with_tz_from_string, usize_from_string,
 };
-use serde::{Deserialize, Serialize};
+use serde::{de, ser::SerializeMap, Deserialize, Deserializer, Serialize, Serializer};
 use std::fmt;
 use uuid::Uuid;
 
@@ -39,7 +39,7 @@ pub struct AccountHistory {
     pub details: AccountHistoryDetails, // variants are not not clear
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum AccountHistoryType {
     Fee,
     Match,
@@ -47,6 +47,9 @@ pub enum AccountHistoryType {
     Transfer,
     Conversion,
     NotSet,
+    /// Value unknown to this version of the crate
+    #[serde(other)]
+    Unknown,
 }
 
 impl Default for AccountHistoryType {
@@ -57,6 +60,7 @@ impl Default for AccountHistoryType {
 
 #[non_exhaustive]
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(remote = "Self")]
 #[serde(tag = "type", content = "details")]
 #[serde(rename_all = "camelCase")]
 pub enum AccountHistoryDetails {
@@ -85,13 +89,60 @@ pub enum AccountHistoryDetails {
     Conversion {
         conversion_id: Uuid,
     },
+    /// Entry type unknown to this version of the crate
+    #[serde(skip)]
+    Unknown {
+        _type: String,
+        details: serde_json::Value,
+    },
+}
+
+impl Serialize for AccountHistoryDetails {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            AccountHistoryDetails::Unknown { _type, details } => {
+                let mut map = serializer.serialize_map(Some(2))?;
+                map.serialize_entry("type", _type)?;
+                map.serialize_entry("details", details)?;
+                map.end()
+            }
+            known => AccountHistoryDetails::serialize(known, serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for AccountHistoryDetails {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct Raw {
+            #[serde(rename = "type")]
+            _type: String,
+            #[serde(default)]
+            details: serde_json::Value,
+        }
+
+        let raw = Raw::deserialize(deserializer)?;
+        const KNOWN: &[&str] = &["fee", "match", "rebate", "transfer", "conversion"];
+        if KNOWN.contains(&raw._type.as_str()) {
+            let value = serde_json::json!({"type": raw._type, "details": raw.details});
+            AccountHistoryDetails::deserialize(&value).map_err(de::Error::custom)
+        } else {
+            Ok(AccountHistoryDetails::Unknown {
+                _type: raw._type,
+                details: raw.details,
+            })
+        }
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[serde(rename_all = "camelCase")]
 pub enum AccountHistoryDetailsTransferType {
     Deposit,
     Withdraw,
+    /// Value unknown to this version of the crate
+    #[serde(other)]
+    Unknown,
 }
 
 impl<'a> From<&'a AccountHistoryDetails> for AccountHistoryType {
@@ -102,6 +153,7 @@ impl<'a> From<&'a AccountHistoryDetails> for AccountHistoryType {
             AccountHistoryDetails::Transfer { .. } => AccountHistoryType::Transfer,
             AccountHistoryDetails::Rebate { .. } => AccountHistoryType::Rebate,
             AccountHistoryDetails::Conversion { .. } => AccountHistoryType::Conversion,
+            AccountHistoryDetails::Unknown { .. } => AccountHistoryType::Unknown,
         }
     }
 }
@@ -119,11 +171,14 @@ pub struct AccountHolds {
     pub _ref: Uuid,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[serde(rename_all = "camelCase")]
 pub enum AccountHoldsType {
     Order,
     Transfer,
+    /// Value unknown to this version of the crate
+    #[serde(other)]
+    Unknown,
 }
 
 // limit:{"id":"e9d0ff7a-ed50-4040-87a7-c884ae562807","price":"1.12000000","size":"1.00000000","product_id":"BTC-USD","side":"buy","stp":"dc","type":"limit","time_in_force":"GTC","post_only":true,"created_at":"2018-08-23T18:53:42.144811Z","fill_fees":"0.0000000000000000","filled_size":"0.00000000","executed_value":"0.0000000000000000","status":"pending","settled":false}
@@ -161,6 +216,7 @@ pub struct Order {
 }
 
 #[derive(Serialize, Deserialize, Debug)]
+#[serde(remote = "Self")]
 #[serde(rename_all = "camelCase")]
 #[serde(tag = "type")]
 pub enum OrderType {
@@ -182,9 +238,39 @@ pub enum OrderType {
         #[serde(deserialize_with = "f64_from_string")]
         funds: f64,
     },
+    /// Order type unknown to this version of the crate
+    #[serde(skip)]
+    Unknown(String),
+}
+
+impl Serialize for OrderType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            OrderType::Unknown(_type) => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry("type", _type)?;
+                map.end()
+            }
+            known => OrderType::serialize(known, serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for OrderType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        match value.get("type").and_then(|x| x.as_str()) {
+            Some("limit") | Some("market") => {
+                OrderType::deserialize(&value).map_err(de::Error::custom)
+            }
+            Some(_type) => Ok(OrderType::Unknown(_type.to_string())),
+            None => Err(de::Error::missing_field("type")),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(remote = "Self")]
 #[serde(tag = "time_in_force")]
 pub enum OrderTimeInForce {
     GTC,
@@ -194,9 +280,38 @@ pub enum OrderTimeInForce {
     },
     IOC,
     FOK,
+    /// Time in force unknown to this version of the crate
+    #[serde(skip)]
+    Unknown(String),
+}
+
+impl Serialize for OrderTimeInForce {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            OrderTimeInForce::Unknown(time_in_force) => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry("time_in_force", time_in_force)?;
+                map.end()
+            }
+            known => OrderTimeInForce::serialize(known, serializer),
+        }
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+impl<'de> Deserialize<'de> for OrderTimeInForce {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        match value.get("time_in_force").and_then(|x| x.as_str()) {
+            Some("GTC") | Some("GTT") | Some("IOC") | Some("FOK") => {
+                OrderTimeInForce::deserialize(&value).map_err(de::Error::custom)
+            }
+            Some(time_in_force) => Ok(OrderTimeInForce::Unknown(time_in_force.to_string())),
+            None => Err(de::Error::missing_field("time_in_force")),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[serde(rename_all = "camelCase")]
 pub enum OrderStatus {
     Open,
@@ -204,6 +319,9 @@ pub enum OrderStatus {
     Pending,
     Active,
     Rejected,
+    /// Value unknown to this version of the crate
+    #[serde(other)]
+    Unknown,
 }
 
 impl fmt::Display for OrderStatus {
@@ -214,6 +332,7 @@ impl fmt::Display for OrderStatus {
             OrderStatus::Pending => "pending",
             OrderStatus::Active => "active",
             OrderStatus::Rejected => "rejected",
+            OrderStatus::Unknown => "unknown",
         };
         write!(f, "{}", res)
     }
@@ -239,11 +358,11 @@ pub struct Fill {
     pub usd_volume: String,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
-pub enum FillLiquidity {
-    M,
-    T,
+string_enum! {
+    pub enum FillLiquidity {
+        M = "M",
+        T = "T",
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -322,23 +441,102 @@ pub struct TransferDetails {
     pub crypto_transaction_id: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "snake_case")]
-pub enum TransferType {
-    Deposit,
-    Withdraw,
-    InternalDeposit,
-    InternalWithdraw,
+string_enum! {
+    pub enum TransferType {
+        Deposit = "deposit",
+        Withdraw = "withdraw",
+        InternalDeposit = "internal_deposit",
+        InternalWithdraw = "internal_withdraw",
+    }
 }
 
-impl fmt::Display for TransferType {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let res = match self {
-            TransferType::Deposit => "deposit",
-            TransferType::Withdraw => "withdraw",
-            TransferType::InternalDeposit => "internal_deposit",
-            TransferType::InternalWithdraw => "internal_withdraw",
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unknown_enums() {
+        let status: OrderStatus = serde_json::from_str(r#""received""#).unwrap();
+        assert_eq!(OrderStatus::Unknown, status);
+        assert_eq!(
+            OrderStatus::Open,
+            serde_json::from_str(r#""open""#).unwrap()
+        );
+        assert_eq!(
+            r#""internal_deposit""#,
+            serde_json::to_string(&TransferType::InternalDeposit).unwrap()
+        );
+        let _type: AccountHistoryType = serde_json::from_str(r#""StakingReward""#).unwrap();
+        assert_eq!(AccountHistoryType::Unknown, _type);
+        assert_eq!(
+            AccountHistoryType::Fee,
+            serde_json::from_str(r#""Fee""#).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_unknown_order_types() {
+        let order = |_type: &str, time_in_force: &str| {
+            format!(
+                r#"{{"id":"e9d0ff7a-ed50-4040-87a7-c884ae562807","price":"1.12000000","size":"1.00000000","product_id":"BTC-USD","side":"buy","stp":"dc","type":"{}","time_in_force":"{}","post_only":true,"created_at":"2018-08-23T18:53:42.144811Z","fill_fees":"0.0000000000000000","filled_size":"0.00000000","executed_value":"0.0000000000000000","status":"pending","settled":false}}"#,
+                _type, time_in_force
+            )
         };
-        write!(f, "{}", res)
+
+        let limit: Order = serde_json::from_str(&order("limit", "GTC")).unwrap();
+        assert!(matches!(
+            limit._type,
+            OrderType::Limit {
+                time_in_force: OrderTimeInForce::GTC,
+                ..
+            }
+        ));
+        let limit: Order = serde_json::from_str(&order("limit", "GTD")).unwrap();
+        match &limit._type {
+            OrderType::Limit {
+                size,
+                time_in_force: OrderTimeInForce::Unknown(time_in_force),
+                ..
+            } => {
+                assert_eq!(1.0, *size);
+                assert_eq!("GTD", time_in_force);
+            }
+            other => panic!("{:?}", other),
+        }
+        let json = serde_json::to_string(&limit).unwrap();
+        assert!(json.contains(r#""time_in_force":"GTD""#));
+
+        let twap: Order = serde_json::from_str(&order("twap", "GTC")).unwrap();
+        assert!(matches!(&twap._type, OrderType::Unknown(x) if x == "twap"));
+        assert_eq!("BTC-USD", twap.product_id);
+        let json = serde_json::to_string(&twap).unwrap();
+        assert!(json.contains(r#""type":"twap""#));
+    }
+
+    #[test]
+    fn test_account_history_details() {
+        let json = r#"[
+            {"id":"1","created_at":"2019-08-21T22:10:15.190000Z","amount":"1","balance":"2",
+             "type":"match","details":{"order_id":"d50ec984-77a8-460a-b958-66f114b0de9b","product_id":"BTC-USD","trade_id":"7"}},
+            {"id":"2","created_at":"2019-08-21T22:10:15.190000Z","amount":"1","balance":"2",
+             "type":"staking_reward","details":{"currency":"ETH"}}
+        ]"#;
+        let hist: Vec<AccountHistory> = serde_json::from_str(json).unwrap();
+        assert!(matches!(
+            hist[0].details,
+            AccountHistoryDetails::Match { trade_id: 7, .. }
+        ));
+        match &hist[1].details {
+            AccountHistoryDetails::Unknown { _type, details } => {
+                assert_eq!("staking_reward", _type);
+                assert_eq!("ETH", details["currency"]);
+            }
+            other => panic!("{:?}", other),
+        }
+        let json = serde_json::to_string(&hist[1].details).unwrap();
+        assert_eq!(
+            r#"{"type":"staking_reward","details":{"currency":"ETH"}}"#,
+            json
+        );
     }
 }
@@ -1,6 +1,15 @@
 use super::DateTime;
-use crate::utils::f64_from_string;
-use serde::{Deserialize, Serialize};
+use crate::utils::{f64_from_string, f64_opt_from_string};
+use chrono::{SecondsFormat, TimeZone, Utc};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::{Borrow, Cow};
+use std::convert::{TryFrom, TryInto};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Write};
+use std::ops::Deref;
+use std::str::FromStr;
+use thiserror::Error;
 use uuid::Uuid;
 
 // Public
@@ -11,7 +20,7 @@ pub struct Time {
     pub epoch: f64,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
 pub struct CurrencyDetails {
     #[serde(rename = "type")]
     pub _type: Option<CurrencyDetailsType>,
@@ -21,20 +30,23 @@ pub struct CurrencyDetails {
     pub crypto_address_link: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[serde(rename_all = "camelCase")]
 pub enum CurrencyDetailsType {
     Crypto,
     Fiat,
+    /// Value unknown to this version of the crate
+    #[serde(other)]
+    Unknown,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Currency {
     pub id: String,
     pub name: String,
     #[serde(deserialize_with = "f64_from_string")]
     pub min_size: f64,
-    pub status: String,
+    pub status: CurrencyStatus,
     pub message: Option<String>,
     #[serde(deserialize_with = "f64_from_string")]
     pub max_precision: f64,
@@ -42,7 +54,7 @@ pub struct Currency {
     pub details: CurrencyDetails,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Product {
     pub id: String,
     pub display_name: String,
@@ -67,13 +79,143 @@ pub struct Product {
     pub auction_mode: bool,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[serde(rename_all = "camelCase")]
 pub enum ProductStatus {
     Online,
     Offline,
     Internal,
     Delisted,
+    /// Value unknown to this version of the crate
+    #[serde(other)]
+    Unknown,
+}
+
+string_enum! {
+    pub enum CurrencyStatus {
+        Online = "online",
+        Offline = "offline",
+        Delisted = "delisted",
+    }
+}
+
+/// Product id in `BASE-QUOTE` form, eg: `BTC-USD`
+///
+/// Checked on construction, derefs to `&str`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(try_from = "String", into = "String")]
+pub struct ProductId {
+    id: String,
+    sep: usize,
+}
+
+impl ProductId {
+    pub fn new(base: &str, quote: &str) -> Result<Self, ParseProductIdError> {
+        format!("{}-{}", base, quote).try_into()
+    }
+
+    pub fn base(&self) -> &str {
+        &self.id[..self.sep]
+    }
+
+    pub fn quote(&self) -> &str {
+        &self.id[self.sep + 1..]
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.id
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error("invalid product id, BASE-QUOTE expected: {0}")]
+pub struct ParseProductIdError(String);
+
+impl FromStr for ProductId {
+    type Err = ParseProductIdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.to_string().try_into()
+    }
+}
+
+impl TryFrom<String> for ProductId {
+    type Error = ParseProductIdError;
+
+    fn try_from(id: String) -> Result<Self, Self::Error> {
+        let valid = |x: &str| !x.is_empty() && x.chars().all(|c| c.is_ascii_alphanumeric());
+        match id.split_once('-') {
+            Some((base, quote)) if valid(base) && valid(quote) => {
+                let sep = base.len();
+                Ok(ProductId { id, sep })
+            }
+            _ => Err(ParseProductIdError(id)),
+        }
+    }
+}
+
+impl From<ProductId> for String {
+    fn from(product_id: ProductId) -> Self {
+        product_id.id
+    }
+}
+
+/// Lets `reqs::Order` builders take a product id
+impl<'a> From<&'a ProductId> for Cow<'a, str> {
+    fn from(product_id: &'a ProductId) -> Self {
+        Cow::Borrowed(&product_id.id)
+    }
+}
+
+impl From<ProductId> for Cow<'static, str> {
+    fn from(product_id: ProductId) -> Self {
+        Cow::Owned(product_id.id)
+    }
+}
+
+impl fmt::Display for ProductId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.id)
+    }
+}
+
+impl Deref for ProductId {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.id
+    }
+}
+
+impl AsRef<str> for ProductId {
+    fn as_ref(&self) -> &str {
+        &self.id
+    }
+}
+
+impl Borrow<str> for ProductId {
+    fn borrow(&self) -> &str {
+        &self.id
+    }
+}
+
+/// Same as `str` hash to be consistent with `Borrow<str>`
+impl Hash for ProductId {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state)
+    }
+}
+
+impl PartialEq<str> for ProductId {
+    fn eq(&self, other: &str) -> bool {
+        self.id == other
+    }
+}
+
+impl PartialEq<&str> for ProductId {
+    fn eq(&self, other: &&str) -> bool {
+        self.id == *other
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -159,15 +301,129 @@ pub struct Trade {
     pub side: super::reqs::OrderSide,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct Candle(
-    pub usize, // time
-    pub f64,   // low
-    pub f64,   // high
-    pub f64,   // open
-    pub f64,   // close
-    pub f64,   // volume
-);
+/// Historic rate bucket
+///
+/// Deserializes from Coinbase array form `[time, low, high, open, close, volume]`
+/// and from named form produced by `Serialize`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(try_from = "CandleRepr")]
+pub struct Candle {
+    /// bucket start time
+    pub time: DateTime,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum CandleRepr {
+    Array(i64, f64, f64, f64, f64, f64),
+    Named {
+        time: DateTime,
+        open: f64,
+        high: f64,
+        low: f64,
+        close: f64,
+        volume: f64,
+    },
+}
+
+impl TryFrom<CandleRepr> for Candle {
+    type Error = String;
+
+    fn try_from(repr: CandleRepr) -> Result<Self, Self::Error> {
+        match repr {
+            CandleRepr::Array(time, low, high, open, close, volume) => {
+                let time = Utc
+                    .timestamp_opt(time, 0)
+                    .single()
+                    .ok_or_else(|| format!("candle time out of range: {}", time))?;
+                Ok(Candle::new(time, open, high, low, close, volume))
+            }
+            CandleRepr::Named {
+                time,
+                open,
+                high,
+                low,
+                close,
+                volume,
+            } => Ok(Candle::new(time, open, high, low, close, volume)),
+        }
+    }
+}
+
+impl Candle {
+    /// Header for [`to_csv`](Candle::to_csv) lines
+    pub const CSV_HEADER: &'static str = "time,open,high,low,close,volume";
+
+    pub fn new(time: DateTime, open: f64, high: f64, low: f64, close: f64, volume: f64) -> Self {
+        Candle {
+            time,
+            open,
+            high,
+            low,
+            close,
+            volume,
+        }
+    }
+
+    /// (high + low + close) / 3
+    pub fn typical_price(&self) -> f64 {
+        (self.high + self.low + self.close) / 3.0
+    }
+
+    /// high - low
+    pub fn range(&self) -> f64 {
+        self.high - self.low
+    }
+
+    /// |close - open|
+    pub fn body(&self) -> f64 {
+        (self.close - self.open).abs()
+    }
+
+    /// close > open
+    pub fn is_bullish(&self) -> bool {
+        self.close > self.open
+    }
+
+    /// Volume weighted average of typical prices, approximation of VWAP for the period
+    pub fn vwap(candles: &[Candle]) -> Option<f64> {
+        let (pv, v) = candles.iter().fold((0.0, 0.0), |(pv, v), x| {
+            (pv + x.typical_price() * x.volume, v + x.volume)
+        });
+        if v > 0.0 {
+            Some(pv / v)
+        } else {
+            None
+        }
+    }
+
+    /// Csv line in [`CSV_HEADER`](Candle::CSV_HEADER) order, time as RFC 3339
+    pub fn to_csv(&self) -> String {
+        format!(
+            "{},{},{},{},{},{}",
+            self.time.to_rfc3339_opts(SecondsFormat::Secs, true),
+            self.open,
+            self.high,
+            self.low,
+            self.close,
+            self.volume
+        )
+    }
+
+    /// Writes header and candles as csv
+    pub fn write_csv<W: Write>(candles: &[Candle], mut writer: W) -> io::Result<()> {
+        writeln!(writer, "{}", Self::CSV_HEADER)?;
+        for candle in candles {
+            writeln!(writer, "{}", candle.to_csv())?;
+        }
+        Ok(())
+    }
+}
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Stats24H {
@@ -179,8 +435,100 @@ pub struct Stats24H {
     pub low: f64,
     #[serde(deserialize_with = "f64_from_string")]
     pub volume: f64,
+    #[serde(default)]
+    #[serde(deserialize_with = "f64_opt_from_string")]
+    pub last: Option<f64>,
+    #[serde(default)]
+    #[serde(deserialize_with = "f64_opt_from_string")]
+    pub volume_30day: Option<f64>,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Stats30Day {
+    #[serde(deserialize_with = "f64_from_string")]
+    pub volume: f64,
+}
+
+/// Item of `Public::get_all_stats`
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ProductStats {
+    pub stats_24hour: Stats24H,
+    pub stats_30day: Stats30Day,
+}
+
+/// Result of `Public::get_candles_range`
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CandleHistory {
+    /// Candles sorted by time, oldest first
+    pub candles: Vec<Candle>,
+    /// Bucket times in the requested range without candle (no trades)
+    pub missing: Vec<DateTime>,
+    pub granularity: Granularity,
+}
+
+impl CandleHistory {
+    pub fn new(
+        mut candles: Vec<Candle>,
+        start: DateTime,
+        end: DateTime,
+        granularity: Granularity,
+    ) -> Self {
+        let step = granularity.as_secs();
+        let from = start.timestamp().div_euclid(step) * step;
+        let to = end.timestamp();
+
+        candles.sort_by_key(|x| x.time);
+        candles.dedup_by_key(|x| x.time);
+        candles.retain(|x| x.time.timestamp() >= from && x.time.timestamp() <= to);
+
+        let mut present = candles.iter().map(|x| x.time.timestamp()).peekable();
+        let missing = (from..=to)
+            .step_by(step as usize)
+            .filter(|time| {
+                while present.next_if(|x| x < time).is_some() {}
+                present.next_if_eq(time).is_none()
+            })
+            .map(|time| Utc.timestamp_opt(time, 0).unwrap())
+            .collect();
+
+        CandleHistory {
+            candles,
+            missing,
+            granularity,
+        }
+    }
+
+    /// Candles with missing buckets filled by previous close and zero volume.
+    /// Missing buckets before the first candle are skipped
+    pub fn filled(&self) -> Vec<Candle> {
+        let step = self.granularity.duration();
+        let flat = |time, close| Candle::new(time, close, close, close, close, 0.0);
+
+        let mut res: Vec<Candle> = Vec::with_capacity(self.candles.len() + self.missing.len());
+        for candle in &self.candles {
+            if let Some(last) = res.last() {
+                let close = last.close;
+                let mut time = last.time + step;
+                while time < candle.time {
+                    res.push(flat(time, close));
+                    time += step;
+                }
+            }
+            res.push(*candle);
+        }
+        if let Some(last) = self.candles.last() {
+            for time in self.missing.iter().filter(|x| **x > last.time) {
+                res.push(flat(*time, last.close));
+            }
+        }
+        res
+    }
+}
+
+/// Max buckets returned by one `get_candles` call
+pub const MAX_CANDLES: usize = 300;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum Granularity {
     M1 = 60,
     M5 = 300,
@@ -190,3 +538,237 @@ pub enum Granularity {
     H6 = 21600,
     D1 = 86400,
 }
+
+impl Granularity {
+    /// All values, finest first
+    pub const ALL: [Granularity; 7] = [
+        Granularity::M1,
+        Granularity::M5,
+        Granularity::M15,
+        Granularity::H1,
+        Granularity::H4,
+        Granularity::H6,
+        Granularity::D1,
+    ];
+
+    pub fn as_secs(self) -> i64 {
+        self as i64
+    }
+
+    pub fn duration(self) -> chrono::Duration {
+        chrono::Duration::seconds(self.as_secs())
+    }
+
+    pub fn from_secs(secs: i64) -> Option<Self> {
+        Self::ALL.iter().copied().find(|x| x.as_secs() == secs)
+    }
+
+    /// Number of buckets covering `start..=end`, `start` is aligned down to the granularity
+    pub fn buckets(self, start: DateTime, end: DateTime) -> usize {
+        let step = self.as_secs();
+        let from = start.timestamp().div_euclid(step) * step;
+        let to = end.timestamp();
+        if to < from {
+            0
+        } else {
+            ((to - from) / step + 1) as usize
+        }
+    }
+
+    /// Finest granularity which covers `start..=end` with one `get_candles` request,
+    /// `None` if window is longer than `MAX_CANDLES` days
+    pub fn finest_for(start: DateTime, end: DateTime) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|x| x.buckets(start, end) <= MAX_CANDLES)
+    }
+}
+
+impl fmt::Display for Granularity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Granularity::M1 => "1m",
+            Granularity::M5 => "5m",
+            Granularity::M15 => "15m",
+            Granularity::H1 => "1h",
+            Granularity::H4 => "4h",
+            Granularity::H6 => "6h",
+            Granularity::D1 => "1d",
+        };
+        f.write_str(s)
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error("unknown granularity: {0}")]
+pub struct ParseGranularityError(String);
+
+/// Parses `1m`, `15m`, `1h`, `1d`, ... or number of seconds: `60`, `900`, ...
+impl FromStr for Granularity {
+    type Err = ParseGranularityError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParseGranularityError(s.to_string());
+        let s = s.trim().to_lowercase();
+        if let Ok(secs) = s.parse::<i64>() {
+            return Self::from_secs(secs).ok_or_else(err);
+        }
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|x| x.to_string() == s)
+            .ok_or_else(err)
+    }
+}
+
+impl From<Granularity> for std::time::Duration {
+    fn from(granularity: Granularity) -> Self {
+        std::time::Duration::from_secs(granularity.as_secs() as u64)
+    }
+}
+
+impl TryFrom<chrono::Duration> for Granularity {
+    type Error = ParseGranularityError;
+
+    fn try_from(duration: chrono::Duration) -> Result<Self, Self::Error> {
+        Self::from_secs(duration.num_seconds())
+            .filter(|x| x.duration() == duration)
+            .ok_or_else(|| ParseGranularityError(format!("{}", duration)))
+    }
+}
+
+impl TryFrom<std::time::Duration> for Granularity {
+    type Error = ParseGranularityError;
+
+    fn try_from(duration: std::time::Duration) -> Result<Self, Self::Error> {
+        Self::from_secs(duration.as_secs() as i64)
+            .filter(|_| duration.subsec_nanos() == 0)
+            .ok_or_else(|| ParseGranularityError(format!("{:?}", duration)))
+    }
+}
+
+/// Serialized as `Display` string
+impl Serialize for Granularity {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Deserialized from string accepted by `FromStr` or number of seconds
+impl<'de> Deserialize<'de> for Granularity {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Secs(i64),
+            Str(String),
+        }
+        match Repr::deserialize(deserializer)? {
+            Repr::Secs(secs) => Self::from_secs(secs)
+                .ok_or_else(|| de::Error::custom(format!("unknown granularity: {}", secs))),
+            Repr::Str(s) => s.parse().map_err(de::Error::custom),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_candle_deserialize() {
+        let candle: Candle =
+            serde_json::from_str("[1577836800, 9.0, 12.0, 10.0, 11.0, 2.5]").unwrap();
+        let time: DateTime = "2020-01-01T00:00:00Z".parse().unwrap();
+        assert_eq!(Candle::new(time, 10.0, 12.0, 9.0, 11.0, 2.5), candle);
+
+        let named: Candle = serde_json::from_str(&serde_json::to_string(&candle).unwrap()).unwrap();
+        assert_eq!(candle, named);
+    }
+
+    #[test]
+    fn test_candle_helpers() {
+        let time: DateTime = "2020-01-01T00:00:00Z".parse().unwrap();
+        let a = Candle::new(time, 10.0, 12.0, 9.0, 9.0, 1.0);
+        let b = Candle::new(time, 9.0, 15.0, 9.0, 12.0, 3.0);
+        assert_eq!(10.0, a.typical_price());
+        assert_eq!(3.0, a.range());
+        assert_eq!(1.0, a.body());
+        assert!(!a.is_bullish());
+        assert_eq!(Some(11.5), Candle::vwap(&[a, b]));
+        assert_eq!(None, Candle::vwap(&[]));
+    }
+
+    #[test]
+    fn test_candle_csv() {
+        let time: DateTime = "2020-01-01T00:00:00Z".parse().unwrap();
+        let mut buf = vec![];
+        Candle::write_csv(&[Candle::new(time, 10.0, 12.0, 9.0, 11.5, 2.0)], &mut buf).unwrap();
+        assert_eq!(
+            "time,open,high,low,close,volume\n2020-01-01T00:00:00Z,10,12,9,11.5,2\n",
+            String::from_utf8(buf).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_granularity_conversions() {
+        assert_eq!(Ok(Granularity::M15), "15m".parse());
+        assert_eq!(Ok(Granularity::H6), " 6H".parse());
+        assert_eq!(Ok(Granularity::D1), "86400".parse());
+        assert!("2m".parse::<Granularity>().is_err());
+        assert_eq!("4h", Granularity::H4.to_string());
+        assert_eq!(std::time::Duration::from_secs(300), Granularity::M5.into());
+        assert_eq!(
+            Ok(Granularity::H1),
+            Granularity::try_from(chrono::Duration::hours(1))
+        );
+        assert!(Granularity::try_from(chrono::Duration::seconds(61)).is_err());
+    }
+
+    #[test]
+    fn test_granularity_serde() {
+        assert_eq!(
+            r#""15m""#,
+            serde_json::to_string(&Granularity::M15).unwrap()
+        );
+        let xs: Vec<Granularity> = serde_json::from_str(r#"["1h", 60, "1d"]"#).unwrap();
+        assert_eq!(vec![Granularity::H1, Granularity::M1, Granularity::D1], xs);
+    }
+
+    #[test]
+    fn test_granularity_finest_for() {
+        let start: DateTime = "2020-01-01T00:00:30Z".parse().unwrap();
+        let end = start + chrono::Duration::minutes(299);
+        assert_eq!(300, Granularity::M1.buckets(start, end));
+        assert_eq!(Some(Granularity::M1), Granularity::finest_for(start, end));
+        let end = start + chrono::Duration::minutes(300);
+        assert_eq!(Some(Granularity::M5), Granularity::finest_for(start, end));
+        let end = start + chrono::Duration::days(30);
+        assert_eq!(Some(Granularity::H4), Granularity::finest_for(start, end));
+        let end = start + chrono::Duration::days(400);
+        assert_eq!(None, Granularity::finest_for(start, end));
+    }
+
+    #[test]
+    fn test_product_id() {
+        let id: ProductId = "BTC-USD".parse().unwrap();
+        assert_eq!(("BTC", "USD"), (id.base(), id.quote()));
+        assert_eq!(Ok(id.clone()), ProductId::new("BTC", "USD"));
+        assert!(ProductId::new("BTC-USD", "EUR").is_err());
+        assert!(ProductId::new("BTC", "").is_err());
+        assert_eq!("BTC-USD", id.to_string());
+        assert!(id == "BTC-USD");
+        assert!("BTCUSD".parse::<ProductId>().is_err());
+        assert!("BTC-".parse::<ProductId>().is_err());
+        assert!("BTC-USD-X".parse::<ProductId>().is_err());
+
+        let ids: Vec<ProductId> = serde_json::from_str(r#"["ETH-BTC"]"#).unwrap();
+        assert_eq!("ETH", ids[0].base());
+        assert_eq!(r#"["ETH-BTC"]"#, serde_json::to_string(&ids).unwrap());
+        assert!(serde_json::from_str::<ProductId>(r#""ETH""#).is_err());
+
+        let map: std::collections::HashMap<_, _> = vec![(id, 1)].into_iter().collect();
+        assert_eq!(Some(&1), map.get("BTC-USD"));
+    }
+}
@@ -7,7 +7,7 @@ use serde::{Deserialize, Serialize};
 pub struct Order<'a> {
     side: OrderSide,
     client_oid: Option<Uuid>,
-    product_id: Cow<'a, str>,
+    pub(crate) product_id: Cow<'a, str>,
     #[serde(flatten)]
     _type: OrderType,
     #[serde(flatten)]
@@ -166,6 +166,31 @@ impl<'a> Order<'a> {
             _ => panic!("time_in_force is for limit orders only"),
         }
     }
+
+    /// Limit order which stays on the book through the auction: GTC, not post-only
+    pub fn auction_limit<T: Into<Cow<'a, str>>>(
+        product_id: T,
+        side: OrderSide,
+        size: f64,
+        price: f64,
+    ) -> Self {
+        Self::limit(product_id, side, size, price, false).time_in_force(OrderTimeInForce::GTC)
+    }
+
+    /// Order can be placed while product is in auction mode: only limit orders without stop,
+    /// resting on the book (GTC or GTT) are accepted during the collection phase
+    pub fn is_auction_compatible(&self) -> bool {
+        match &self._type {
+            OrderType::Limit { time_in_force, .. } => {
+                self.stop.is_none()
+                    && time_in_force
+                        .as_ref()
+                        .map(|x| x.is_auction_compatible())
+                        .unwrap_or(true)
+            }
+            OrderType::Market { .. } => false,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -179,6 +204,13 @@ pub enum OrderTimeInForce {
     FOK,
 }
 
+impl OrderTimeInForce {
+    /// Order rests on the book, IOC and FOK are rejected in auction mode
+    pub fn is_auction_compatible(&self) -> bool {
+        matches!(self, OrderTimeInForce::GTC | OrderTimeInForce::GTT { .. })
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
 #[serde(rename_all = "camelCase")]
 pub enum OrderTimeInForceCancelAfter {
@@ -204,6 +236,7 @@ pub enum OrderStopType {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::structs::public::ProductId;
 
     #[test]
     fn test_order_builder() {
@@ -261,5 +294,26 @@ mod tests {
         assert_eq!(order_owned.product_id, "AAA-BBB");
         let order_str: Order = Order::buy_market("AAA-BBB", 10.0);
         assert_eq!(order_str.product_id, "AAA-BBB");
+        let product_id: ProductId = "AAA-BBB".parse().unwrap();
+        let order_id = Order::sell_limit(&product_id, 10.0, 1.0, true);
+        assert_eq!(order_id.product_id, "AAA-BBB");
+        let order_id_owned = Order::buy_market(product_id, 10.0);
+        assert_eq!(order_id_owned.product_id, "AAA-BBB");
+    }
+
+    #[test]
+    fn test_auction_compatible() {
+        let o = Order::auction_limit("BTC-USD", OrderSide::Buy, 1.0, 100.0);
+        assert!(o.is_auction_compatible());
+        let json = serde_json::to_string(&o).unwrap();
+        assert!(json.contains(r#""time_in_force":"GTC""#));
+
+        assert!(Order::buy_limit("BTC-USD", 1.0, 100.0, true).is_auction_compatible());
+        assert!(!Order::buy_market("BTC-USD", 1.0).is_auction_compatible());
+        let ioc =
+            Order::buy_limit("BTC-USD", 1.0, 100.0, false).time_in_force(OrderTimeInForce::IOC);
+        assert!(!ioc.is_auction_compatible());
+        let stop = Order::buy_limit("BTC-USD", 1.0, 100.0, false).stop_entry(90.0);
+        assert!(!stop.is_auction_compatible());
     }
 }
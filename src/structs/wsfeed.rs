@@ -1,11 +1,17 @@
+use super::public::{Currency, CurrencyDetails, CurrencyStatus, Product, ProductStatus};
 use super::DateTime;
 use crate::utils::{
-    f64_from_string, f64_nan_from_string, f64_opt_from_string, uuid_opt_from_string,
+    bool_from_yes_no, datetime_from_epoch, f64_from_string, f64_nan_from_string,
+    f64_opt_from_string, uuid_opt_from_string,
 };
 use serde::{Deserialize, Deserializer, Serialize};
+use std::fmt;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
 use uuid::Uuid;
 
-#[derive(Serialize, Deserialize, Debug)]
+pub use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Auth {
     pub signature: String,
     pub key: String,
@@ -13,7 +19,7 @@ pub struct Auth {
     pub timestamp: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Subscribe {
     #[serde(rename = "type")]
     pub _type: SubscribeCmd,
@@ -23,13 +29,14 @@ pub struct Subscribe {
     pub auth: Option<Auth>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub enum SubscribeCmd {
     Subscribe,
+    Unsubscribe,
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(untagged)]
 pub enum Channel {
     Name(ChannelType),
@@ -39,16 +46,43 @@ pub enum Channel {
     },
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
-#[serde(rename_all = "camelCase")]
-pub enum ChannelType {
-    Heartbeat,
-    Status,
-    Ticker,
-    Level2,
-    Matches,
-    Full,
-    User,
+/// Close frame sent by the server
+#[derive(Debug, Clone, PartialEq)]
+pub struct CloseReason {
+    pub code: CloseCode,
+    pub reason: String,
+}
+
+impl From<CloseFrame<'_>> for CloseReason {
+    fn from(frame: CloseFrame<'_>) -> Self {
+        CloseReason {
+            code: frame.code,
+            reason: frame.reason.into_owned(),
+        }
+    }
+}
+
+impl fmt::Display for CloseReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.code, self.reason)
+    }
+}
+
+string_enum! {
+    pub enum ChannelType {
+        Heartbeat = "heartbeat",
+        Status = "status",
+        Ticker = "ticker",
+        Level2 = "level2",
+        Matches = "matches",
+        Full = "full",
+        User = "user",
+        Level2Batch = "level2_batch",
+        TickerBatch = "ticker_batch",
+        Level2Fifty = "level2_50",
+        Auctionfeed = "auctionfeed",
+        RfqMatches = "rfq_matches",
+    }
 }
 
 #[derive(Deserialize, Debug)]
@@ -80,6 +114,8 @@ pub(crate) enum InputMessage {
         time: DateTime,
     },
     LastMatch(Match),
+    Auction(Auction),
+    RfqMatch(RfqMatch),
     Received(Received),
     Open(Open),
     Done(Done),
@@ -90,6 +126,46 @@ pub(crate) enum InputMessage {
         message: String,
     },
     InternalError(crate::CBError), // in futures 0.3 probably TryStream
+    #[serde(untagged)]
+    Unknown(UnknownMessage),
+}
+
+/// Message with `type` unknown to this version of the crate, known types which fail to parse
+/// are rejected instead of falling back here
+#[derive(Debug)]
+pub(crate) struct UnknownMessage(serde_json::Value);
+
+impl<'de> Deserialize<'de> for UnknownMessage {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        const KNOWN: &[&str] = &[
+            "subscriptions",
+            "heartbeat",
+            "status",
+            "ticker",
+            "snapshot",
+            "l2update",
+            "last_match",
+            "auction",
+            "rfq_match",
+            "received",
+            "open",
+            "done",
+            "match",
+            "activate",
+            "change",
+            "error",
+            "internal_error",
+        ];
+        let value = serde_json::Value::deserialize(deserializer)?;
+        match value.get("type").and_then(|x| x.as_str()) {
+            Some(_type) if KNOWN.contains(&_type) => Err(serde::de::Error::custom(format!(
+                "invalid `{}` message",
+                _type
+            ))),
+            Some(_) => Ok(UnknownMessage(value)),
+            None => Err(serde::de::Error::missing_field("type")),
+        }
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -111,13 +187,85 @@ pub enum Message {
     Level2(Level2),
     Match(Match),
     Full(Full),
+    Auction(Auction),
+    RfqMatch(RfqMatch),
     Error {
         message: String,
     },
     InternalError(crate::CBError), // in futures 0.3 probably TryStream
+    /// Pong to the client ping of `WSFeed::connect_with_ping` with its round-trip time
+    Pong {
+        rtt: std::time::Duration,
+    },
+    /// Message with `type` unknown to this version of the crate, as raw json
+    Unknown(serde_json::Value),
+}
+
+impl Message {
+    /// Parses websocket text frame, frames of unknown `type` become `Message::Unknown`
+    pub fn from_json(text: &str) -> Result<Message, serde_json::Error> {
+        crate::json::from_str::<InputMessage>(text).map(Message::from)
+    }
+
+    /// Parses owned websocket frame, with `simd-json` in place without a copy
+    pub(crate) fn from_json_mut(frame: &mut [u8]) -> Result<Message, serde_json::Error> {
+        crate::json::from_slice_mut::<InputMessage>(frame).map(Message::from)
+    }
+
+    /// Exchange time of the message
+    pub fn time(&self) -> Option<&DateTime> {
+        match self {
+            Message::Heartbeat { time, .. } => Some(time),
+            Message::Ticker(x) => x.time(),
+            Message::Level2(x) => x.time(),
+            Message::Match(x) => Some(&x.time),
+            Message::Full(x) => x.time(),
+            Message::Auction(x) => Some(&x.timestamp),
+            Message::RfqMatch(x) => Some(&x.time),
+            _ => None,
+        }
+    }
+
+    /// Channel which sent the message, batch channels are reported as the base one and
+    /// `user` as `full`
+    pub fn channel(&self) -> Option<ChannelType> {
+        let channel = match self {
+            Message::Heartbeat { .. } => ChannelType::Heartbeat,
+            Message::Status { .. } => ChannelType::Status,
+            Message::Ticker(_) => ChannelType::Ticker,
+            Message::Level2(_) => ChannelType::Level2,
+            Message::Match(_) => ChannelType::Matches,
+            Message::Full(_) => ChannelType::Full,
+            Message::Auction(_) => ChannelType::Auctionfeed,
+            Message::RfqMatch(_) => ChannelType::RfqMatches,
+            _ => return None,
+        };
+        Some(channel)
+    }
+}
+
+/// Message with local receive metadata, item of `WSFeed::connect_with_envelope`
+#[derive(Debug, PartialEq)]
+pub struct Envelope {
+    pub message: Message,
+    /// Local time the frame was received
+    pub received: DateTime,
+    /// Monotonic time the frame was received, for intervals between messages
+    pub instant: tokio::time::Instant,
+    /// Id of the connection, unique within the process
+    pub connection_id: u64,
+    /// Size of the raw frame in bytes
+    pub frame_size: usize,
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+impl Envelope {
+    /// Exchange-to-local latency, negative if the local clock is behind
+    pub fn latency(&self) -> Option<chrono::Duration> {
+        self.message.time().map(|time| self.received - *time)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum Level2 {
     Snapshot {
         product_id: String,
@@ -147,7 +295,7 @@ impl Level2 {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct StatusProduct {
     pub id: String,
     pub base_currency: String,
@@ -157,30 +305,80 @@ pub struct StatusProduct {
     #[serde(deserialize_with = "f64_from_string")]
     pub quote_increment: f64,
     pub display_name: String,
-    pub status: String,
+    pub status: ProductStatus,
     pub status_message: String,
     #[serde(deserialize_with = "f64_from_string")]
     pub min_market_funds: f64,
     pub post_only: bool,
     pub limit_only: bool,
     pub cancel_only: bool,
-    pub fx_stablecoin: bool
+    pub fx_stablecoin: bool,
+    #[serde(default)]
+    pub margin_enabled: bool,
+    #[serde(default)]
+    pub trading_disabled: bool,
+    #[serde(default)]
+    #[serde(deserialize_with = "f64_from_string")]
+    pub max_slippage_percentage: f64,
+    #[serde(default)]
+    pub auction_mode: bool,
+}
+
+impl From<StatusProduct> for Product {
+    fn from(x: StatusProduct) -> Self {
+        Product {
+            id: x.id,
+            display_name: x.display_name,
+            base_currency: x.base_currency,
+            quote_currency: x.quote_currency,
+            quote_increment: x.quote_increment,
+            base_increment: x.base_increment,
+            min_market_funds: x.min_market_funds,
+            margin_enabled: x.margin_enabled,
+            status: x.status,
+            status_message: x.status_message,
+            cancel_only: x.cancel_only,
+            limit_only: x.limit_only,
+            post_only: x.post_only,
+            trading_disabled: x.trading_disabled,
+            fx_stablecoin: x.fx_stablecoin,
+            max_slippage_percentage: x.max_slippage_percentage,
+            auction_mode: x.auction_mode,
+        }
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct StatusCurrency {
     pub id: String,
     pub name: String,
     #[serde(deserialize_with = "f64_from_string")]
     pub min_size: f64,
-    pub status: String,
+    pub status: CurrencyStatus,
     pub status_message: String,
     #[serde(deserialize_with = "f64_from_string")]
     pub max_precision: f64,
     pub convertible_to: Vec<String>,
+    #[serde(default)]
+    pub details: CurrencyDetails,
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+impl From<StatusCurrency> for Currency {
+    fn from(x: StatusCurrency) -> Self {
+        Currency {
+            id: x.id,
+            name: x.name,
+            min_size: x.min_size,
+            status: x.status,
+            message: Some(x.status_message),
+            max_precision: x.max_precision,
+            convertible_to: Some(x.convertible_to),
+            details: x.details,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Level2SnapshotRecord {
     #[serde(deserialize_with = "f64_from_string")]
     pub price: f64,
@@ -188,7 +386,7 @@ pub struct Level2SnapshotRecord {
     pub size: f64,
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Level2UpdateRecord {
     pub side: super::reqs::OrderSide,
     #[serde(deserialize_with = "f64_from_string")]
@@ -197,7 +395,7 @@ pub struct Level2UpdateRecord {
     pub size: f64,
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(untagged)]
 #[serde(rename_all = "camelCase")]
 pub enum Ticker {
@@ -215,6 +413,21 @@ pub enum Ticker {
         best_bid: f64,
         #[serde(deserialize_with = "f64_nan_from_string")]
         best_ask: f64,
+        #[serde(default)]
+        #[serde(deserialize_with = "f64_opt_from_string")]
+        open_24h: Option<f64>,
+        #[serde(default)]
+        #[serde(deserialize_with = "f64_opt_from_string")]
+        volume_24h: Option<f64>,
+        #[serde(default)]
+        #[serde(deserialize_with = "f64_opt_from_string")]
+        low_24h: Option<f64>,
+        #[serde(default)]
+        #[serde(deserialize_with = "f64_opt_from_string")]
+        high_24h: Option<f64>,
+        #[serde(default)]
+        #[serde(deserialize_with = "f64_opt_from_string")]
+        volume_30d: Option<f64>,
     },
     Empty {
         sequence: usize,
@@ -268,7 +481,7 @@ impl Ticker {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum Full {
     Received(Received),
     Open(Open),
@@ -332,7 +545,7 @@ impl Full {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(tag = "order_type")]
 #[serde(rename_all = "camelCase")]
 pub enum Received {
@@ -367,7 +580,7 @@ pub enum Received {
     },
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Open {
     pub time: DateTime,
     pub product_id: String,
@@ -384,7 +597,7 @@ pub struct Open {
     pub profile_id: Option<Uuid>,
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(untagged)]
 pub enum Done {
     Limit {
@@ -413,14 +626,68 @@ pub enum Done {
     },
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
-#[serde(rename_all = "camelCase")]
-pub enum Reason {
-    Filled,
-    Canceled,
+string_enum! {
+    pub enum Reason {
+        Filled = "filled",
+        Canceled = "canceled",
+    }
+}
+
+/// Message of `auctionfeed` channel, sent while product is in auction mode
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Auction {
+    pub product_id: String,
+    pub sequence: usize,
+    pub auction_state: AuctionState,
+    #[serde(default)]
+    #[serde(deserialize_with = "f64_opt_from_string")]
+    pub best_bid_price: Option<f64>,
+    #[serde(default)]
+    #[serde(deserialize_with = "f64_opt_from_string")]
+    pub best_bid_size: Option<f64>,
+    #[serde(default)]
+    #[serde(deserialize_with = "f64_opt_from_string")]
+    pub best_ask_price: Option<f64>,
+    #[serde(default)]
+    #[serde(deserialize_with = "f64_opt_from_string")]
+    pub best_ask_size: Option<f64>,
+    /// indicative open price
+    #[serde(default)]
+    #[serde(deserialize_with = "f64_opt_from_string")]
+    pub open_price: Option<f64>,
+    #[serde(default)]
+    #[serde(deserialize_with = "f64_opt_from_string")]
+    pub open_size: Option<f64>,
+    /// auction can be opened at `open_price`
+    #[serde(deserialize_with = "bool_from_yes_no")]
+    pub can_open: bool,
+    #[serde(deserialize_with = "datetime_from_epoch")]
+    pub timestamp: DateTime,
+}
+
+string_enum! {
+    pub enum AuctionState {
+        /// orders are collected, no matching
+        Collection = "collection",
+    }
+}
+
+/// Message of `rfq_matches` channel
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RfqMatch {
+    pub trade_id: usize,
+    pub maker_order_id: Uuid,
+    pub taker_order_id: Uuid,
+    pub time: DateTime,
+    pub product_id: String,
+    #[serde(deserialize_with = "f64_from_string")]
+    pub size: f64,
+    #[serde(deserialize_with = "f64_from_string")]
+    pub price: f64,
+    pub side: super::reqs::OrderSide,
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Match {
     pub trade_id: usize,
     pub sequence: usize,
@@ -451,7 +718,7 @@ pub struct Match {
     pub profile_id: Option<Uuid>,
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Change {
     pub time: DateTime,
     pub sequence: usize,
@@ -477,7 +744,7 @@ pub struct Change {
     pub profile_id: Option<Uuid>,
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Activate {
     pub product_id: String,
     #[serde(deserialize_with = "f64_from_string")]
@@ -497,11 +764,11 @@ pub struct Activate {
     pub profile_id: Option<Uuid>,
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
-#[serde(rename_all = "camelCase")]
-pub enum StopType {
-    Entry,
-    Exit,
+string_enum! {
+    pub enum StopType {
+        Entry = "entry",
+        Exit = "exit",
+    }
 }
 
 impl From<InputMessage> for Message {
@@ -546,6 +813,8 @@ impl From<InputMessage> for Message {
                 products
             },
             InputMessage::LastMatch(_match) => Message::Match(_match),
+            InputMessage::Auction(auction) => Message::Auction(auction),
+            InputMessage::RfqMatch(rfq_match) => Message::RfqMatch(rfq_match),
             InputMessage::Received(_match) => Message::Full(Full::Received(_match)),
             InputMessage::Open(open) => Message::Full(Full::Open(open)),
             InputMessage::Done(done) => Message::Full(Full::Done(done)),
@@ -554,6 +823,7 @@ impl From<InputMessage> for Message {
             InputMessage::Activate(activate) => Message::Full(Full::Activate(activate)),
             InputMessage::Error { message } => Message::Error { message },
             InputMessage::InternalError(err) => Message::InternalError(err),
+            InputMessage::Unknown(UnknownMessage(value)) => Message::Unknown(value),
         }
     }
 }
@@ -563,7 +833,7 @@ impl<'de> Deserialize<'de> for Message {
     where
         D: Deserializer<'de>,
     {
-        Deserialize::deserialize(deserializer).map(|input_msg: InputMessage| input_msg.into())
+        InputMessage::deserialize(deserializer).map(Message::from)
     }
 }
 
@@ -675,4 +945,96 @@ mod tests {
 
         assert!(s.uuid.is_none());
     }
+
+    #[test]
+    fn test_parse_status() {
+        let json = r#"{
+            "type": "status",
+            "products": [{
+                "id": "BTC-USD", "base_currency": "BTC", "quote_currency": "USD",
+                "base_increment": "0.00000001", "quote_increment": "0.01",
+                "display_name": "BTC/USD", "status": "online", "status_message": "",
+                "min_market_funds": "10", "post_only": false, "limit_only": false,
+                "cancel_only": false, "fx_stablecoin": false, "margin_enabled": false,
+                "trading_disabled": false, "max_slippage_percentage": "0.03",
+                "auction_mode": true
+            }],
+            "currencies": [{
+                "id": "XYZ", "name": "Xyz", "min_size": "0.01", "status": "paused",
+                "status_message": "", "max_precision": "0.01", "convertible_to": [],
+                "details": {"type": "crypto", "symbol": "X"}
+            }]
+        }"#;
+        let (products, currencies) = match serde_json::from_str(json).unwrap() {
+            InputMessage::Status {
+                products,
+                currencies,
+            } => (products, currencies),
+            _ => panic!("status expected"),
+        };
+
+        let product = Product::from(products[0].clone());
+        assert_eq!(ProductStatus::Online, product.status);
+        assert_eq!(0.03, product.max_slippage_percentage);
+        assert!(product.auction_mode);
+
+        let currency = Currency::from(currencies[0].clone());
+        assert_eq!(
+            CurrencyStatus::Unknown("paused".to_string()),
+            currency.status
+        );
+        assert_eq!(Some("X".to_string()), currency.details.symbol);
+        assert_eq!(
+            r#""paused""#,
+            serde_json::to_string(&currency.status).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_unknown() {
+        let json = r#"{"type":"new_channel_message","product_id":"BTC-USD","x":1}"#;
+        let msg = Message::from_json(json).unwrap();
+        match &msg {
+            Message::Unknown(value) => assert_eq!("BTC-USD", value["product_id"]),
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(msg, serde_json::from_str(json).unwrap());
+
+        let json = r#"{"type":"done","side":"sell","order_id":"d50ec984-77a8-460a-b958-66f114b0de9b","reason":"expired","product_id":"BTC-USD","sequence":10,"remaining_size":"1.0","price":"1.0","time":"2019-08-21T22:10:15.190000Z"}"#;
+        match Message::from_json(json).unwrap() {
+            Message::Full(Full::Done(Done::Limit { reason, .. })) => {
+                assert_eq!(Reason::Unknown("expired".to_string()), reason)
+            }
+            other => panic!("{:?}", other),
+        }
+        assert!(Message::from_json(r#"{"type":"heartbeat"}"#).is_err());
+        assert!(Message::from_json(r#"{"product_id":"BTC-USD"}"#).is_err());
+    }
+
+    #[test]
+    fn test_parse_auction() {
+        let json = r#"{"type":"auction","product_id":"LTC-USD","sequence":3262786978,"auction_state":"collection","best_bid_price":"333.98","best_bid_size":"4.39088265","best_ask_price":"333.99","best_ask_size":"25.23542881","open_price":"333.99","open_size":"0.193","can_open":"yes","timestamp":"1547096186.718"}"#;
+        match Message::from_json(json).unwrap() {
+            Message::Auction(auction) => {
+                assert_eq!(Some(333.99), auction.open_price);
+                assert_eq!(1547096186718, auction.timestamp.timestamp_millis());
+                assert_eq!(AuctionState::Collection, auction.auction_state);
+                assert!(auction.can_open);
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_rfq_match() {
+        let json = r#"{"type":"rfq_match","maker_order_id":"ac928c66-ca53-498f-9c13-a110027a60e8","taker_order_id":"132fb6ae-456b-4654-b4e0-d681ac05cea1","time":"2014-11-07T08:19:27.028459Z","trade_id":30,"product_id":"BTC-USD","size":"5.23512","price":"400.23","side":"sell"}"#;
+        match Message::from_json(json).unwrap() {
+            Message::RfqMatch(m) => assert_eq!((30, 400.23), (m.trade_id, m.price)),
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(
+            r#""level2_50""#,
+            serde_json::to_string(&ChannelType::Level2Fifty).unwrap()
+        );
+    }
 }
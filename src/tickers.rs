@@ -0,0 +1,315 @@
+//! Contains cache of the latest ticker of every product
+//!
+//! [`TickerCache`] keeps only the latest values from the `ticker` channel, optionally seeded from
+//! REST `get_ticker` with [`load`](TickerCache::load). Reads don't await the feed, changes are
+//! broadcast to [`subscribe`](TickerCache::subscribe)rs.
+//!
+//! ```no_run
+//! use coinbase_pro_rs::{structs::wsfeed::ChannelType, tickers::TickerCache, ASync, Public};
+//! use coinbase_pro_rs::{WSFeed, MAIN_URL, WS_URL};
+//!
+//! # async fn run() {
+//! let products = ["BTC-USD", "ETH-USD"];
+//! let client: Public<ASync> = Public::new(MAIN_URL);
+//! let cache = TickerCache::load(&client, &products).await.unwrap();
+//! let stream = WSFeed::connect(WS_URL, &products, &[ChannelType::Ticker])
+//!     .await
+//!     .unwrap();
+//! cache.clone().spawn_feed(stream);
+//!
+//! let price = cache.price("BTC-USD");
+//! # }
+//! ```
+
+use futures::StreamExt;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+use crate::api::PublicApi;
+use crate::structs::public;
+use crate::structs::wsfeed::{Message, Ticker};
+use crate::structs::DateTime;
+use crate::wsfeed::CBStream;
+use crate::CBError;
+
+const EVENTS_CAPACITY: usize = 1024;
+
+/// Latest known values of one product, fields missing in the source stay `None`
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LatestTicker {
+    pub product_id: String,
+    /// `None` until a ticker with price is received
+    pub price: Option<f64>,
+    pub best_bid: Option<f64>,
+    pub best_ask: Option<f64>,
+    pub last_size: Option<f64>,
+    pub trade_id: Option<usize>,
+    /// Feed sequence, `None` for tickers loaded from REST
+    pub sequence: Option<usize>,
+    pub time: Option<DateTime>,
+    pub open_24h: Option<f64>,
+    pub high_24h: Option<f64>,
+    pub low_24h: Option<f64>,
+    pub volume_24h: Option<f64>,
+    pub volume_30d: Option<f64>,
+}
+
+impl LatestTicker {
+    /// Midpoint of best bid and ask
+    pub fn mid(&self) -> Option<f64> {
+        Some((self.best_bid? + self.best_ask?) / 2.0)
+    }
+
+    fn update(&mut self, ticker: &Ticker) {
+        match ticker {
+            Ticker::Full {
+                trade_id,
+                sequence,
+                time,
+                price,
+                last_size,
+                best_bid,
+                best_ask,
+                open_24h,
+                volume_24h,
+                low_24h,
+                high_24h,
+                volume_30d,
+                ..
+            } => {
+                self.price = Some(*price);
+                self.trade_id = Some(*trade_id);
+                self.sequence = Some(*sequence);
+                self.time = Some(*time);
+                self.last_size = Some(*last_size);
+                self.best_bid = Some(*best_bid).filter(|x| !x.is_nan());
+                self.best_ask = Some(*best_ask).filter(|x| !x.is_nan());
+                self.open_24h = open_24h.or(self.open_24h);
+                self.volume_24h = volume_24h.or(self.volume_24h);
+                self.low_24h = low_24h.or(self.low_24h);
+                self.high_24h = high_24h.or(self.high_24h);
+                self.volume_30d = volume_30d.or(self.volume_30d);
+            }
+            Ticker::Empty {
+                sequence, price, ..
+            } => {
+                if !price.is_nan() {
+                    self.price = Some(*price);
+                }
+                self.sequence = Some(*sequence);
+            }
+        }
+    }
+}
+
+impl From<(&str, public::Ticker)> for LatestTicker {
+    fn from((product_id, ticker): (&str, public::Ticker)) -> Self {
+        LatestTicker {
+            product_id: product_id.to_string(),
+            price: Some(ticker.price),
+            best_bid: Some(ticker.bid),
+            best_ask: Some(ticker.ask),
+            last_size: Some(ticker.size),
+            trade_id: Some(ticker.trade_id),
+            time: Some(ticker.time),
+            volume_24h: Some(ticker.volume),
+            ..Default::default()
+        }
+    }
+}
+
+/// Shared latest tickers, clones share the same data
+#[derive(Clone)]
+pub struct TickerCache {
+    tickers: Arc<RwLock<HashMap<String, LatestTicker>>>,
+    events: broadcast::Sender<LatestTicker>,
+}
+
+impl Default for TickerCache {
+    fn default() -> Self {
+        let (events, _) = broadcast::channel(EVENTS_CAPACITY);
+        TickerCache {
+            tickers: Arc::default(),
+            events,
+        }
+    }
+}
+
+impl TickerCache {
+    /// Empty cache, filled by `ticker` messages
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cache seeded from `get_ticker` of every product
+    pub async fn load(api: &impl PublicApi, product_ids: &[&str]) -> Result<Self, CBError> {
+        let cache = Self::new();
+        for product_id in product_ids {
+            let ticker = api.get_ticker(product_id).await?;
+            cache.insert(LatestTicker::from((*product_id, ticker)));
+        }
+        Ok(cache)
+    }
+
+    /// Applies messages of `stream` until it ends, errors are logged
+    pub fn spawn_feed<S>(self, mut stream: S) -> JoinHandle<()>
+    where
+        S: CBStream + 'static,
+    {
+        tokio::spawn(async move {
+            while let Some(msg) = stream.next().await {
+                match msg {
+                    Ok(msg) => {
+                        self.push_message(&msg);
+                    }
+                    Err(e) => log::warn!("ticker feed: {}", e),
+                }
+            }
+        })
+    }
+
+    /// Applies `Message::Ticker`, skips others
+    pub fn push_message(&self, msg: &Message) -> bool {
+        match msg {
+            Message::Ticker(ticker) => self.push_ticker(ticker),
+            _ => false,
+        }
+    }
+
+    /// Returns true if the values changed, tickers older than the cached one are skipped
+    pub fn push_ticker(&self, ticker: &Ticker) -> bool {
+        let latest = {
+            let mut tickers = self.tickers.write().unwrap();
+            let latest = tickers
+                .entry(ticker.product_id().to_string())
+                .or_insert_with(|| LatestTicker {
+                    product_id: ticker.product_id().to_string(),
+                    ..Default::default()
+                });
+            if latest.sequence.map(|x| x >= *ticker.sequence()) == Some(true) {
+                return false;
+            }
+            let mut old = latest.clone();
+            latest.update(ticker);
+            // a new sequence alone is not a change
+            old.sequence = latest.sequence;
+            if *latest == old {
+                return false;
+            }
+            latest.clone()
+        };
+        // no subscribers is not an error
+        let _ = self.events.send(latest);
+        true
+    }
+
+    /// Replaces values of the product, e.g. from REST
+    pub fn insert(&self, ticker: LatestTicker) {
+        self.tickers
+            .write()
+            .unwrap()
+            .insert(ticker.product_id.clone(), ticker.clone());
+        let _ = self.events.send(ticker);
+    }
+
+    /// Receiver of changes made after this call
+    pub fn subscribe(&self) -> broadcast::Receiver<LatestTicker> {
+        self.events.subscribe()
+    }
+
+    pub fn get(&self, product_id: &str) -> Option<LatestTicker> {
+        self.tickers.read().unwrap().get(product_id).cloned()
+    }
+
+    pub fn price(&self, product_id: &str) -> Option<f64> {
+        self.tickers
+            .read()
+            .unwrap()
+            .get(product_id)
+            .and_then(|x| x.price)
+    }
+
+    pub fn all(&self) -> Vec<LatestTicker> {
+        let mut tickers: Vec<_> = self.tickers.read().unwrap().values().cloned().collect();
+        tickers.sort_by(|a, b| a.product_id.cmp(&b.product_id));
+        tickers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ticker(sequence: usize, price: &str) -> Message {
+        Message::from_json(&format!(
+            r#"{{"type":"ticker","trade_id":20153558,"sequence":{},"time":"2019-08-21T22:10:15.190000Z",
+                "product_id":"BTC-USD","price":"{}","side":"buy","last_size":"0.01","best_bid":"9999.00",
+                "best_ask":"10000.00","open_24h":"9500.00","volume_24h":"1234.5","low_24h":"9400.00",
+                "high_24h":"10100.00","volume_30d":"45678.9"}}"#,
+            sequence, price
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_push() {
+        let cache = TickerCache::new();
+        let mut rx = cache.subscribe();
+        assert!(cache.push_message(&ticker(2, "10000.00")));
+        let latest = cache.get("BTC-USD").unwrap();
+        assert_eq!(Some(9999.5), latest.mid());
+        assert_eq!(Some(9400.0), latest.low_24h);
+        assert_eq!(Some(45678.9), latest.volume_30d);
+
+        assert!(!cache.push_message(&ticker(1, "9000.00")));
+        assert!(!cache.push_message(&ticker(3, "10000.00")));
+        assert!(cache.push_message(&ticker(4, "10001.00")));
+        assert_eq!(Some(10001.0), cache.price("BTC-USD"));
+
+        let prices: Vec<_> = std::iter::from_fn(|| rx.try_recv().ok())
+            .map(|x| x.price)
+            .collect();
+        assert_eq!(vec![Some(10000.0), Some(10001.0)], prices);
+        assert_eq!(None, cache.price("ETH-USD"));
+    }
+
+    #[test]
+    fn test_push_without_price() {
+        let cache = TickerCache::new();
+        let empty = |sequence| Ticker::Empty {
+            sequence,
+            product_id: "ETH-USD".to_string(),
+            price: f64::NAN,
+        };
+        assert!(!cache.push_ticker(&empty(1)));
+        assert!(!cache.push_ticker(&empty(2)));
+        assert_eq!(None, cache.get("ETH-USD").unwrap().price);
+        assert_eq!(None, cache.price("ETH-USD"));
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_load() {
+        use crate::{mock::MockServer, ASync, Public};
+        use hyper::Method;
+
+        let server = MockServer::start();
+        server.mock_json(
+            Method::GET,
+            "/products/BTC-USD/ticker",
+            r#"{"trade_id":4729088,"price":"333.99","size":"0.193","bid":"333.98","ask":"333.99",
+                "volume":"5957.11914015","time":"2015-11-14T20:46:03.511254Z"}"#,
+        );
+        let client: Public<ASync> = Public::new(&server.uri());
+        let cache = TickerCache::load(&client, &["BTC-USD"]).await.unwrap();
+        let latest = cache.get("BTC-USD").unwrap();
+        assert_eq!(Some(333.99), latest.price);
+        assert_eq!(Some(5957.11914015), latest.volume_24h);
+        assert_eq!(None, latest.sequence);
+
+        assert!(cache.push_message(&ticker(1, "334.00")));
+        assert_eq!(Some(1), cache.get("BTC-USD").unwrap().sequence);
+    }
+}
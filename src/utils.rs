@@ -101,6 +101,42 @@ where
     (s + "").parse().map_err(de::Error::custom)
 }
 
+/// `"yes"`/`"no"` or bool
+pub fn bool_from_yes_no<'de, D>(d: D) -> Result<bool, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum YesNo {
+        Bool(bool),
+        Str(String),
+    }
+    match YesNo::deserialize(d)? {
+        YesNo::Bool(x) => Ok(x),
+        YesNo::Str(s) => match s.as_str() {
+            "yes" => Ok(true),
+            "no" => Ok(false),
+            _ => Err(de::Error::invalid_value(
+                de::Unexpected::Str(&s),
+                &"yes or no",
+            )),
+        },
+    }
+}
+
+/// Unix time in seconds with fraction, as number or string: `"1547096186.718"`
+pub fn datetime_from_epoch<'de, D>(d: D) -> Result<super::structs::DateTime, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let secs = d.deserialize_any(F64InQuotes)?;
+    let millis = (secs * 1000.0).round() as i64;
+    chrono::TimeZone::timestamp_millis_opt(&Utc, millis)
+        .single()
+        .ok_or_else(|| de::Error::custom(format!("timestamp out of range: {}", secs)))
+}
+
 pub fn datetime_with_tz_from_string<'de, D>(d: D) -> Result<super::structs::DateTime, D::Error>
     where
         D: Deserializer<'de>,
@@ -124,6 +160,20 @@ pub fn option_datetime_with_tz_from_string<'de, D>(d: D) -> Result<Option<super:
     Ok(v.map(|Wrapper(a)| a))
 }
 
+/// Percent-encodes everything except unreserved characters, for path segments and query values
+pub(crate) fn encode(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                res.push(b as char)
+            }
+            _ => res.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    res
+}
+
 #[cfg(test)]
 static DELAY_TIMEOUT: u64 = 200;
 
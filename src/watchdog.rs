@@ -0,0 +1,417 @@
+//! Contains heartbeat watchdog for the websocket feed
+//!
+//! A half-open TCP connection never yields an error, the stream just stops. [`Watchdog`]
+//! subscribes to the `heartbeat` channel, remembers the latest heartbeat of every product and
+//! yields `WSError::Stale` when any subscribed product has no heartbeat within the timeout. With
+//! [`reconnect`](Watchdog::reconnect) the feed is reconnected with the same subscription instead.
+//!
+//! ```no_run
+//! use coinbase_pro_rs::{structs::wsfeed::*, watchdog::Watchdog, WS_URL};
+//! use futures::StreamExt;
+//! use std::time::Duration;
+//!
+//! # async fn run() {
+//! let subscribe = Subscribe {
+//!     _type: SubscribeCmd::Subscribe,
+//!     product_ids: vec!["BTC-USD".to_string()],
+//!     channels: vec![Channel::Name(ChannelType::Matches)],
+//!     auth: None,
+//! };
+//! let watchdog = Watchdog::new(Duration::from_secs(5)).reconnect(Duration::from_secs(1));
+//! let mut stream = watchdog.connect(WS_URL, subscribe).await.unwrap();
+//! while let Some(msg) = stream.next().await {
+//!     println!("{:?}", msg);
+//! }
+//! # }
+//! ```
+
+use futures::stream::{self, BoxStream, StreamExt};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::structs::wsfeed::{Channel, ChannelType, Message, Subscribe};
+use crate::structs::DateTime;
+use crate::wsfeed::CBStream;
+use crate::{CBError, WSError, WSFeed};
+
+/// Latest heartbeat of one product
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeartbeatState {
+    pub sequence: usize,
+    pub last_trade_id: usize,
+    /// Exchange time of the heartbeat
+    pub time: DateTime,
+    /// Local time the heartbeat was received
+    pub received: Instant,
+}
+
+/// Heartbeat tracker and stale feed detector, clones share the same state
+#[derive(Clone)]
+pub struct Watchdog {
+    timeout: Duration,
+    reconnect: Option<Duration>,
+    heartbeats: Arc<RwLock<HashMap<String, HeartbeatState>>>,
+}
+
+impl Watchdog {
+    /// Feed is stale when no heartbeat arrives within `timeout`
+    pub fn new(timeout: Duration) -> Self {
+        Watchdog {
+            timeout,
+            reconnect: None,
+            heartbeats: Arc::default(),
+        }
+    }
+
+    /// Reconnects `delay` after a stale feed or connection error instead of ending the stream
+    pub fn reconnect(mut self, delay: Duration) -> Self {
+        self.reconnect = Some(delay);
+        self
+    }
+
+    pub fn heartbeat(&self, product_id: &str) -> Option<HeartbeatState> {
+        self.heartbeats.read().unwrap().get(product_id).cloned()
+    }
+
+    pub fn heartbeats(&self) -> HashMap<String, HeartbeatState> {
+        self.heartbeats.read().unwrap().clone()
+    }
+
+    /// Products without heartbeat for longer than the timeout
+    pub fn stale_products(&self) -> Vec<String> {
+        let mut res: Vec<_> = self
+            .heartbeats
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(_, x)| x.received.elapsed() > self.timeout)
+            .map(|(id, _)| id.clone())
+            .collect();
+        res.sort();
+        res
+    }
+
+    /// Records `Message::Heartbeat`, returns false for other messages
+    pub fn push_message(&self, msg: &Message) -> bool {
+        match msg {
+            Message::Heartbeat {
+                sequence,
+                last_trade_id,
+                product_id,
+                time,
+            } => {
+                let state = HeartbeatState {
+                    sequence: *sequence,
+                    last_trade_id: *last_trade_id,
+                    time: *time,
+                    received: Instant::now(),
+                };
+                self.heartbeats
+                    .write()
+                    .unwrap()
+                    .insert(product_id.clone(), state);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Passes messages of `stream` through, yields `WSError::Stale` and ends when a product has
+    /// no heartbeat within the timeout. The stream has to be subscribed to the `heartbeat` channel,
+    /// products are taken from `subscriptions` and `heartbeat` messages.
+    pub fn watch<S>(&self, stream: S) -> impl CBStream
+    where
+        S: CBStream + 'static,
+    {
+        self.watch_boxed(stream.boxed(), &[])
+    }
+
+    /// Connects with the `heartbeat` channel added to `subscribe` and watches the feed,
+    /// reconnects if [`reconnect`](Watchdog::reconnect) is set.
+    /// `auth` is sent unchanged on reconnect, so its signature may expire.
+    pub async fn connect(&self, uri: &str, subscribe: Subscribe) -> Result<impl CBStream, CBError> {
+        let subscribe = with_heartbeat(subscribe);
+        let stream = WSFeed::connect_with_sub(uri, subscribe.clone()).await?;
+        let first = self.watch_boxed(stream.boxed(), &subscribe.product_ids);
+
+        let delay = match self.reconnect {
+            Some(delay) => delay,
+            None => return Ok(first),
+        };
+
+        let watchdog = self.clone();
+        let uri = uri.to_string();
+        let stream = stream::unfold(Some(first), move |mut conn| {
+            let watchdog = watchdog.clone();
+            let uri = uri.clone();
+            let subscribe = subscribe.clone();
+            async move {
+                loop {
+                    let stream = match conn.as_mut() {
+                        Some(stream) => stream,
+                        None => {
+                            tokio::time::sleep(delay).await;
+                            match WSFeed::connect_with_sub(&uri, subscribe.clone()).await {
+                                Ok(stream) => {
+                                    log::info!("websocket reconnected");
+                                    conn = Some(
+                                        watchdog
+                                            .watch_boxed(stream.boxed(), &subscribe.product_ids),
+                                    );
+                                    continue;
+                                }
+                                Err(e) => return Some((Err(e), None)),
+                            }
+                        }
+                    };
+                    match stream.next().await {
+                        Some(Err(e @ CBError::Websocket(_))) => {
+                            log::warn!("websocket reconnecting: {}", e);
+                            return Some((Err(e), None));
+                        }
+                        Some(msg) => return Some((msg, conn)),
+                        None => conn = None,
+                    }
+                }
+            }
+        });
+        Ok(stream.boxed())
+    }
+
+    fn watch_boxed(
+        &self,
+        stream: BoxStream<'static, Result<Message, CBError>>,
+        product_ids: &[String],
+    ) -> BoxStream<'static, Result<Message, CBError>> {
+        let watchdog = self.clone();
+        let last = LastHeartbeats::new(product_ids);
+        stream::unfold(Some((stream, last)), move |state| {
+            let watchdog = watchdog.clone();
+            async move {
+                let (mut stream, mut last) = state?;
+                let (_, earliest) = last.earliest();
+                match tokio::time::timeout_at(earliest + watchdog.timeout, stream.next()).await {
+                    Ok(Some(Ok(msg))) => {
+                        watchdog.push_message(&msg);
+                        last.push_message(&msg);
+                        Some((Ok(msg), Some((stream, last))))
+                    }
+                    Ok(Some(Err(e))) => Some((Err(e), Some((stream, last)))),
+                    Ok(None) => None,
+                    Err(_) => {
+                        let (product_id, earliest) = last.earliest();
+                        let stale = WSError::Stale(product_id.cloned(), earliest.elapsed());
+                        Some((Err(CBError::Websocket(stale)), None))
+                    }
+                }
+            }
+        })
+        .boxed()
+    }
+}
+
+/// Local time of the latest heartbeat of every watched product
+struct LastHeartbeats {
+    /// Deadline base until a product is known
+    start: Instant,
+    products: HashMap<String, Instant>,
+}
+
+impl LastHeartbeats {
+    fn new(product_ids: &[String]) -> Self {
+        let start = Instant::now();
+        LastHeartbeats {
+            start,
+            products: product_ids.iter().map(|x| (x.clone(), start)).collect(),
+        }
+    }
+
+    /// Heartbeat refreshes its product, `subscriptions` replaces the watched products
+    fn push_message(&mut self, msg: &Message) {
+        let now = Instant::now();
+        match msg {
+            Message::Heartbeat { product_id, .. } => {
+                self.products.insert(product_id.clone(), now);
+            }
+            Message::Subscriptions { channels } => {
+                let subscribed: Vec<&String> = channels
+                    .iter()
+                    .filter_map(|x| match x {
+                        Channel::WithProduct {
+                            name: ChannelType::Heartbeat,
+                            product_ids,
+                        } => Some(product_ids),
+                        _ => None,
+                    })
+                    .flatten()
+                    .collect();
+                self.products.retain(|id, _| subscribed.contains(&id));
+                for id in subscribed {
+                    self.products.entry(id.clone()).or_insert(now);
+                }
+                if self.products.is_empty() {
+                    self.start = now;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Product with the oldest heartbeat, `None` if no product is known yet
+    fn earliest(&self) -> (Option<&String>, Instant) {
+        self.products
+            .iter()
+            .min_by_key(|(_, x)| **x)
+            .map(|(id, x)| (Some(id), *x))
+            .unwrap_or((None, self.start))
+    }
+}
+
+/// Adds the `heartbeat` channel to `subscribe` if it is missing
+pub fn with_heartbeat(mut subscribe: Subscribe) -> Subscribe {
+    let subscribed = subscribe.channels.iter().any(|x| match x {
+        Channel::Name(name) | Channel::WithProduct { name, .. } => *name == ChannelType::Heartbeat,
+    });
+    if !subscribed {
+        subscribe
+            .channels
+            .push(Channel::Name(ChannelType::Heartbeat));
+    }
+    subscribe
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::wsfeed::SubscribeCmd;
+
+    fn heartbeat(last_trade_id: usize) -> Message {
+        Message::Heartbeat {
+            sequence: 10,
+            last_trade_id,
+            product_id: "BTC-USD".to_string(),
+            time: "2019-08-21T22:10:15.190000Z".parse().unwrap(),
+        }
+    }
+
+    fn subscribe(channels: Vec<Channel>) -> Subscribe {
+        Subscribe {
+            _type: SubscribeCmd::Subscribe,
+            product_ids: vec!["BTC-USD".to_string()],
+            channels,
+            auth: None,
+        }
+    }
+
+    #[test]
+    fn test_with_heartbeat() {
+        let sub = with_heartbeat(subscribe(vec![Channel::Name(ChannelType::Ticker)]));
+        assert_eq!(
+            vec![
+                Channel::Name(ChannelType::Ticker),
+                Channel::Name(ChannelType::Heartbeat)
+            ],
+            sub.channels
+        );
+        assert_eq!(2, with_heartbeat(sub).channels.len());
+    }
+
+    #[tokio::test]
+    async fn test_watch() {
+        let watchdog = Watchdog::new(Duration::from_millis(50));
+        let feed =
+            stream::iter(vec![Ok(heartbeat(20)), Ok(heartbeat(21))]).chain(stream::pending());
+        let mut stream = watchdog.watch(feed);
+
+        assert_eq!(heartbeat(20), stream.next().await.unwrap().unwrap());
+        assert_eq!(heartbeat(21), stream.next().await.unwrap().unwrap());
+        assert_eq!(21, watchdog.heartbeat("BTC-USD").unwrap().last_trade_id);
+        assert!(watchdog.stale_products().is_empty());
+
+        match stream.next().await {
+            Some(Err(CBError::Websocket(WSError::Stale(product_id, elapsed)))) => {
+                assert_eq!(Some("BTC-USD".to_string()), product_id);
+                assert!(elapsed >= Duration::from_millis(50))
+            }
+            other => panic!("{:?}", other),
+        }
+        assert!(stream.next().await.is_none());
+        assert_eq!(vec!["BTC-USD".to_string()], watchdog.stale_products());
+    }
+
+    #[tokio::test]
+    async fn test_watch_per_product() {
+        let watchdog = Watchdog::new(Duration::from_millis(100));
+        let subscriptions = Message::Subscriptions {
+            channels: vec![Channel::WithProduct {
+                name: ChannelType::Heartbeat,
+                product_ids: vec!["BTC-USD".to_string(), "ETH-USD".to_string()],
+            }],
+        };
+        let heartbeats = stream::unfold(20, |x| async move {
+            tokio::time::sleep(Duration::from_millis(30)).await;
+            Some((Ok(heartbeat(x)), x + 1))
+        });
+        let feed = stream::iter(vec![Ok(subscriptions)]).chain(heartbeats.boxed());
+        let mut stream = watchdog.watch(feed);
+
+        loop {
+            match stream.next().await {
+                Some(Ok(_)) => {}
+                Some(Err(CBError::Websocket(WSError::Stale(product_id, elapsed)))) => {
+                    assert_eq!(Some("ETH-USD".to_string()), product_id);
+                    assert!(elapsed >= Duration::from_millis(100));
+                    break;
+                }
+                other => panic!("{:?}", other),
+            }
+        }
+        assert!(watchdog.heartbeat("BTC-USD").unwrap().last_trade_id >= 22);
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_watch_without_products() {
+        let watchdog = Watchdog::new(Duration::from_millis(20));
+        let mut stream = watchdog.watch(stream::pending());
+        assert!(matches!(
+            stream.next().await,
+            Some(Err(CBError::Websocket(WSError::Stale(None, _))))
+        ));
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_reconnect() {
+        use crate::mock::MockServer;
+
+        let server = MockServer::start();
+        server.ws_script(
+            r#"{"type":"heartbeat","last_trade_id":20,"product_id":"BTC-USD","sequence":10,
+                "time":"2019-08-21T22:10:15.190000Z"}"#,
+        );
+        let watchdog =
+            Watchdog::new(Duration::from_millis(100)).reconnect(Duration::from_millis(10));
+        let mut stream = watchdog
+            .connect(&server.ws_uri(), subscribe(vec![]))
+            .await
+            .unwrap();
+
+        let subscriptions = Message::Subscriptions {
+            channels: vec![Channel::WithProduct {
+                name: ChannelType::Heartbeat,
+                product_ids: vec!["BTC-USD".to_string()],
+            }],
+        };
+        assert_eq!(subscriptions, stream.next().await.unwrap().unwrap());
+        assert_eq!(heartbeat(20), stream.next().await.unwrap().unwrap());
+        assert!(matches!(
+            stream.next().await,
+            Some(Err(CBError::Websocket(WSError::Stale(Some(_), _))))
+        ));
+        assert_eq!(subscriptions, stream.next().await.unwrap().unwrap());
+        assert_eq!(20, watchdog.heartbeat("BTC-USD").unwrap().last_trade_id);
+    }
+}
@@ -1,26 +1,41 @@
 //! Contains structure which provides futures::Stream to websocket-feed of Coinbase api
 
 use async_trait::async_trait;
-use futures::{future, Sink, Stream};
-use futures_util::{sink::SinkExt, stream::TryStreamExt};
+use chrono::Utc;
+use futures::{ready, Future, Sink, Stream};
+use futures_util::sink::SinkExt;
 use hyper::Method;
 use serde_json;
-use std::time::{SystemTime, UNIX_EPOCH};
-use tokio_tungstenite::{connect_async, tungstenite::Message as TMessage};
+use std::io::{self, Write};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc as std_mpsc, Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::{Instant, Sleep};
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{Error as TError, Message as TMessage},
+};
 use url::Url;
 
-use crate::{private::Private, structs::wsfeed::*, ASync, CBError, WSError};
+use crate::structs::{wsfeed::*, DateTime};
+use crate::{private::Private, replay::Recorder, ASync, CBError, WSError};
 
 pub struct WSFeed;
 
-fn convert_msg(msg: TMessage) -> Message {
+static CONNECTION_ID: AtomicU64 = AtomicU64::new(0);
+
+pub(crate) fn convert_msg(msg: TMessage) -> Message {
     match msg {
-        TMessage::Text(str) => serde_json::from_str(&str).unwrap_or_else(|e| {
-            Message::InternalError(CBError::Serde {
-                error: e,
-                data: str,
+        TMessage::Text(str) => {
+            let mut data = str.into_bytes();
+            Message::from_json_mut(&mut data).unwrap_or_else(|e| {
+                let data = String::from_utf8(data)
+                    .unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned());
+                Message::InternalError(CBError::Serde { error: e, data })
             })
-        }),
+        }
         _ => unreachable!(), // filtered in stream
     }
 }
@@ -51,6 +66,43 @@ impl WSFeed {
         uri: &str,
         subscribe: Subscribe,
     ) -> Result<impl CBStream + CBSink, CBError> {
+        Self::connect_frames(uri, subscribe, None).await
+    }
+
+    // Constructor for extended subcription which pings the server every `interval`,
+    // pongs are received as `Message::Pong` with the round-trip time
+    pub async fn connect_with_ping(
+        uri: &str,
+        subscribe: Subscribe,
+        interval: Duration,
+    ) -> Result<impl CBStream + CBSink, CBError> {
+        Self::connect_frames(uri, subscribe, Some(interval)).await
+    }
+
+    // Constructor for extended subcription which wraps every message into `Envelope` with
+    // local receive time, connection id and frame size
+    pub async fn connect_with_envelope(
+        uri: &str,
+        subscribe: Subscribe,
+    ) -> Result<impl Stream<Item = Result<Envelope, CBError>> + CBSink, CBError> {
+        let mut frames = Self::connect_frames(uri, subscribe, None).await?;
+        frames.stamp = true;
+        Ok(Enveloped {
+            frames,
+            connection_id: CONNECTION_ID.fetch_add(1, Ordering::Relaxed),
+        })
+    }
+
+    async fn connect_frames(
+        uri: &str,
+        subscribe: Subscribe,
+        ping: Option<Duration>,
+    ) -> Result<
+        Frames<
+            impl Stream<Item = Result<TMessage, TError>> + Sink<TMessage, Error = TError> + Unpin + Send,
+        >,
+        CBError,
+    > {
         let url = Url::parse(uri).unwrap();
 
         let stream = connect_async(url)
@@ -59,11 +111,7 @@ impl WSFeed {
             .0;
         log::debug!("WebSocket handshake has been successfully completed");
 
-        let mut stream = stream
-            .try_filter(|msg| future::ready(msg.is_text()))
-            .map_ok(convert_msg)
-            .sink_map_err(|e| CBError::Websocket(WSError::Send(e)))
-            .map_err(|e| CBError::Websocket(WSError::Read(e)));
+        let mut stream = Frames::new(stream, ping);
 
         let subscribe = serde_json::to_string(&subscribe).unwrap();
         stream.send(TMessage::Text(subscribe)).await?;
@@ -72,6 +120,21 @@ impl WSFeed {
         Ok(stream)
     }
 
+    // Constructor for extended subcription which also records every received text frame to
+    // `writer` in a background thread, the recording can be played back with `replay::replay`
+    pub async fn connect_with_sub_recorded<W>(
+        uri: &str,
+        subscribe: Subscribe,
+        writer: W,
+    ) -> Result<impl CBStream + CBSink, CBError>
+    where
+        W: Write + Send + Unpin + 'static,
+    {
+        let mut frames = Self::connect_frames(uri, subscribe, None).await?;
+        frames.tap = Some(Tap::spawn(Recorder::new(writer)));
+        Ok(frames)
+    }
+
     // Constructor for simple subcription with product_ids and channels with auth
     pub async fn connect_with_auth(
         uri: &str,
@@ -111,6 +174,271 @@ impl WSFeed {
     }
 }
 
+/// Converts websocket frames to messages: text frames are parsed, pings are answered by
+/// tungstenite on the next poll, close frame ends the stream with `WSError::Closed`
+struct Frames<S> {
+    inner: S,
+    ping: Option<Ping>,
+    closed: bool,
+    /// Keep `Stamp` of the last frame
+    stamp: bool,
+    last: Option<Stamp>,
+    tap: Option<Tap>,
+}
+
+/// Copies text frames with receive time to a recorder running in its own thread,
+/// so a slow writer doesn't block the stream
+struct Tap {
+    tx: std_mpsc::Sender<(DateTime, String)>,
+    error: Arc<Mutex<Option<io::Error>>>,
+}
+
+impl Tap {
+    fn spawn<W: Write + Send + 'static>(mut recorder: Recorder<W>) -> Self {
+        let (tx, rx) = std_mpsc::channel::<(DateTime, String)>();
+        let error = Arc::new(Mutex::new(None));
+        let error2 = error.clone();
+        std::thread::spawn(move || {
+            for (time, frame) in rx {
+                if let Err(e) = recorder.record_at(time, &frame) {
+                    *error2.lock().unwrap() = Some(e);
+                    return;
+                }
+            }
+        });
+        Tap { tx, error }
+    }
+
+    /// Error of the writer if it stopped
+    fn send(&self, frame: &str) -> Result<(), io::Error> {
+        if self.tx.send((Utc::now(), frame.to_string())).is_ok() {
+            return Ok(());
+        }
+        let error = self.error.lock().unwrap().take();
+        Err(error.unwrap_or_else(|| io::Error::new(io::ErrorKind::BrokenPipe, "recorder stopped")))
+    }
+}
+
+/// Receive time and size of a frame
+struct Stamp {
+    received: DateTime,
+    instant: Instant,
+    size: usize,
+}
+
+impl Stamp {
+    fn new(size: usize) -> Self {
+        Stamp {
+            received: Utc::now(),
+            instant: Instant::now(),
+            size,
+        }
+    }
+}
+
+struct Ping {
+    interval: Duration,
+    timer: Pin<Box<Sleep>>,
+    pending: Option<Vec<u8>>,
+    counter: u64,
+    sent: Option<(Vec<u8>, Instant)>,
+}
+
+impl<S> Frames<S>
+where
+    S: Stream<Item = Result<TMessage, TError>> + Sink<TMessage, Error = TError> + Unpin,
+{
+    fn new(inner: S, ping: Option<Duration>) -> Self {
+        let ping = ping.map(|interval| Ping {
+            interval,
+            timer: Box::pin(tokio::time::sleep(interval)),
+            pending: None,
+            counter: 0,
+            sent: None,
+        });
+        Frames {
+            inner,
+            ping,
+            closed: false,
+            stamp: false,
+            last: None,
+            tap: None,
+        }
+    }
+
+    /// Sends the ping when it is due and flushes pending frames
+    fn poll_ping(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), TError>> {
+        let ping = match self.ping.as_mut() {
+            Some(ping) => ping,
+            None => return Poll::Ready(Ok(())),
+        };
+        if ping.timer.as_mut().poll(cx).is_ready() {
+            ping.counter += 1;
+            ping.pending = Some(ping.counter.to_be_bytes().to_vec());
+            let next = Instant::now() + ping.interval;
+            ping.timer.as_mut().reset(next);
+            // register waker of the new deadline
+            let _ = ping.timer.as_mut().poll(cx);
+        }
+        if ping.pending.is_some() {
+            if let Poll::Ready(res) = Pin::new(&mut self.inner).poll_ready(cx) {
+                res?;
+                let data = ping.pending.take().unwrap_or_default();
+                ping.sent = Some((data.clone(), Instant::now()));
+                Pin::new(&mut self.inner).start_send(TMessage::Ping(data))?;
+            }
+        }
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn pong(&mut self, data: &[u8]) -> Option<Message> {
+        let ping = self.ping.as_mut()?;
+        match ping.sent.take() {
+            Some((sent, at)) if sent == data => Some(Message::Pong { rtt: at.elapsed() }),
+            sent => {
+                ping.sent = sent;
+                None
+            }
+        }
+    }
+}
+
+impl<S> Stream for Frames<S>
+where
+    S: Stream<Item = Result<TMessage, TError>> + Sink<TMessage, Error = TError> + Unpin,
+{
+    type Item = Result<Message, CBError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.closed {
+            return Poll::Ready(None);
+        }
+        if let Poll::Ready(Err(e)) = this.poll_ping(cx) {
+            return Poll::Ready(Some(Err(CBError::Websocket(WSError::Send(e)))));
+        }
+        loop {
+            let msg = match ready!(Pin::new(&mut this.inner).poll_next(cx)) {
+                Some(Ok(msg)) => msg,
+                Some(Err(e)) => {
+                    return Poll::Ready(Some(Err(CBError::Websocket(WSError::Read(e)))))
+                }
+                None => return Poll::Ready(None),
+            };
+            if this.stamp {
+                this.last = Some(Stamp::new(msg.len()));
+            }
+            match msg {
+                TMessage::Text(ref text) => {
+                    if let Some(tap) = &this.tap {
+                        if let Err(e) = tap.send(text) {
+                            this.tap = None;
+                            return Poll::Ready(Some(Err(CBError::Io(e))));
+                        }
+                    }
+                    return Poll::Ready(Some(Ok(convert_msg(msg))));
+                }
+                TMessage::Close(frame) => {
+                    log::debug!("close frame received: {:?}", frame);
+                    this.closed = true;
+                    let reason = frame.map(CloseReason::from);
+                    return Poll::Ready(Some(Err(CBError::Websocket(WSError::Closed(reason)))));
+                }
+                TMessage::Pong(data) => {
+                    if let Some(msg) = this.pong(&data) {
+                        return Poll::Ready(Some(Ok(msg)));
+                    }
+                }
+                TMessage::Ping(_) | TMessage::Binary(_) => {}
+            }
+        }
+    }
+}
+
+impl<S> Sink<TMessage> for Frames<S>
+where
+    S: Sink<TMessage, Error = TError> + Unpin,
+{
+    type Error = CBError;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), CBError>> {
+        Pin::new(&mut self.inner)
+            .poll_ready(cx)
+            .map_err(|e| CBError::Websocket(WSError::Send(e)))
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: TMessage) -> Result<(), CBError> {
+        Pin::new(&mut self.inner)
+            .start_send(item)
+            .map_err(|e| CBError::Websocket(WSError::Send(e)))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), CBError>> {
+        Pin::new(&mut self.inner)
+            .poll_flush(cx)
+            .map_err(|e| CBError::Websocket(WSError::Send(e)))
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), CBError>> {
+        Pin::new(&mut self.inner)
+            .poll_close(cx)
+            .map_err(|e| CBError::Websocket(WSError::Send(e)))
+    }
+}
+
+/// `Frames` yielding messages in `Envelope`
+struct Enveloped<S> {
+    frames: Frames<S>,
+    connection_id: u64,
+}
+
+impl<S> Stream for Enveloped<S>
+where
+    S: Stream<Item = Result<TMessage, TError>> + Sink<TMessage, Error = TError> + Unpin,
+{
+    type Item = Result<Envelope, CBError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let msg = match ready!(Pin::new(&mut this.frames).poll_next(cx)) {
+            Some(Ok(msg)) => msg,
+            Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+            None => return Poll::Ready(None),
+        };
+        let stamp = this.frames.last.take().unwrap_or_else(|| Stamp::new(0));
+        Poll::Ready(Some(Ok(Envelope {
+            message: msg,
+            received: stamp.received,
+            instant: stamp.instant,
+            connection_id: this.connection_id,
+            frame_size: stamp.size,
+        })))
+    }
+}
+
+impl<S> Sink<TMessage> for Enveloped<S>
+where
+    S: Sink<TMessage, Error = TError> + Unpin,
+{
+    type Error = CBError;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), CBError>> {
+        Pin::new(&mut self.frames).poll_ready(cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: TMessage) -> Result<(), CBError> {
+        Pin::new(&mut self.frames).start_send(item)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), CBError>> {
+        Pin::new(&mut self.frames).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), CBError>> {
+        Pin::new(&mut self.frames).poll_close(cx)
+    }
+}
+
 impl<T> CBSink for T where T: Sink<TMessage, Error = CBError> + Unpin + Send {}
 
 #[async_trait]
@@ -206,7 +534,7 @@ mod tests {
             .await
             .unwrap();
         stream
-            .take(2 )
+            .take(2)
             .try_for_each(|msg| {
                 println!("{:?}", msg);
                 let str = format!("{:?}", msg);
@@ -571,4 +899,89 @@ mod tests {
             println!("{:?}", msg);
         }
     }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_feed_ping_close() {
+        use crate::mock::MockServer;
+
+        let server = MockServer::start();
+        let subscribe = Subscribe {
+            _type: SubscribeCmd::Subscribe,
+            product_ids: vec!["BTC-USD".to_string()],
+            channels: vec![Channel::Name(ChannelType::Heartbeat)],
+            auth: None,
+        };
+        let mut stream = WSFeed::connect_with_ping(
+            &server.ws_uri(),
+            subscribe,
+            std::time::Duration::from_millis(50),
+        )
+        .await
+        .unwrap();
+        assert!(matches!(
+            stream.next().await,
+            Some(Ok(Message::Subscriptions { .. }))
+        ));
+
+        server.ws_ping(b"server");
+        assert!(matches!(
+            stream.next().await,
+            Some(Ok(Message::Pong { .. }))
+        ));
+        assert_eq!(vec![b"server".to_vec()], server.ws_pongs());
+
+        server.ws_close(1001, "going away");
+        loop {
+            match stream.next().await {
+                Some(Ok(Message::Pong { .. })) => continue,
+                Some(Err(CBError::Websocket(WSError::Closed(reason)))) => {
+                    assert_eq!(
+                        Some(CloseReason {
+                            code: CloseCode::Away,
+                            reason: "going away".to_string()
+                        }),
+                        reason
+                    );
+                    break;
+                }
+                other => panic!("{:?}", other),
+            }
+        }
+        assert!(stream.next().await.is_none());
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_feed_envelope() {
+        use crate::mock::MockServer;
+
+        let server = MockServer::start();
+        let subscribe = Subscribe {
+            _type: SubscribeCmd::Subscribe,
+            product_ids: vec!["BTC-USD".to_string()],
+            channels: vec![Channel::Name(ChannelType::Heartbeat)],
+            auth: None,
+        };
+        let mut first = WSFeed::connect_with_envelope(&server.ws_uri(), subscribe.clone())
+            .await
+            .unwrap();
+        let mut second = WSFeed::connect_with_envelope(&server.ws_uri(), subscribe)
+            .await
+            .unwrap();
+        let subscribed = first.next().await.unwrap().unwrap();
+        assert_eq!(None, subscribed.latency());
+        second.next().await.unwrap().unwrap();
+
+        let frame = r#"{"type":"heartbeat","last_trade_id":20,"product_id":"BTC-USD","sequence":10,
+                "time":"2019-08-21T22:10:15.190000Z"}"#;
+        server.ws_send(frame);
+        let envelope = first.next().await.unwrap().unwrap();
+        assert!(matches!(envelope.message, Message::Heartbeat { .. }));
+        assert_eq!(frame.len(), envelope.frame_size);
+        assert!(envelope.instant >= subscribed.instant);
+        assert!(envelope.latency().unwrap() > chrono::Duration::zero());
+        let other = second.next().await.unwrap().unwrap();
+        assert_ne!(envelope.connection_id, other.connection_id);
+    }
 }